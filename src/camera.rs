@@ -1,63 +1,836 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, render::camera::CAMERA_2D, transform::TransformSystem};
+
+use crate::prelude::{Contacts, Facing, PlayerIndex, Velocity};
 
 /// The target for the camera to follow
 #[derive(Debug, Clone, Component)]
 pub struct CameraTarget(pub Entity);
 
+/// A target of the camera, the weight is how strongly it pulls the center towards itself
+#[derive(Debug, Clone, Copy)]
+pub struct WeightedTarget {
+    pub entity: Entity,
+    pub weight: f32,
+}
+
+impl WeightedTarget {
+    /// Create a target with the given weight
+    pub fn new(entity: Entity, weight: f32) -> Self {
+        Self { entity, weight }
+    }
+}
+
+/// The targets for the camera to keep in view, used instead of [`CameraTarget`] when present
+/// The camera follows the weighted center of the targets and zooms out to fit them
+#[derive(Debug, Default, Clone, Component)]
+pub struct CameraTargets(pub Vec<WeightedTarget>);
+
+/// The range the camera zooms in, relative to the default view
+/// Higher values zoom out, so a max of 2 shows up to twice the default width
+#[derive(Debug, Clone, Component)]
+pub struct CameraZoom {
+    pub min: f32,
+    pub max: f32,
+    // The time in seconds the zoom takes to get halfway to its goal
+    pub half_life: f32,
+}
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        Self {
+            min: 1.0,
+            max: 2.0,
+            half_life: 0.25,
+        }
+    }
+}
+
+/// What the camera does when it loses its targets
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub enum CameraFallback {
+    // Stay where the camera is
+    Hold,
+    // Follow the player with the lowest index that is still there
+    NextTarget,
+    // Move to the spawn point
+    SpawnPoint(Vec2),
+}
+
+impl Default for CameraFallback {
+    fn default() -> Self {
+        Self::Hold
+    }
+}
+
+/// Sent when a target of a camera is despawned
+#[derive(Debug, Clone)]
+pub struct CameraTargetLost {
+    pub camera: Entity,
+    pub target: Entity,
+}
+
+/// The margin in world units kept around the camera targets
+const FRAMING_MARGIN: f32 = 48.0;
+
 /// The camera offset
 #[derive(Debug, Default, Clone, Component)]
 pub struct CameraOffset(pub Vec2);
 
-/// The lerping speed for the camera
+/// The area of a level or room the camera view is kept inside, in world coordinates
+/// When rooms overlap, the smallest room containing the target is used
+#[derive(Debug, Clone, Component)]
+pub struct CameraBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl CameraBounds {
+    /// Check if the point is inside the bounds
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    /// The area of the bounds
+    pub fn area(&self) -> f32 {
+        let size = self.max - self.min;
+        size.x * size.y
+    }
+
+    /// Clamp the center of a view with the given half size so the view stays inside the bounds
+    /// The view is centered on an axis where the bounds are smaller than the view
+    pub fn clamp(&self, center: Vec2, half_size: Vec2) -> Vec2 {
+        let min = self.min + half_size;
+        let max = self.max - half_size;
+        Vec2::new(
+            if min.x <= max.x {
+                center.x.clamp(min.x, max.x)
+            } else {
+                (self.min.x + self.max.x) / 2.0
+            },
+            if min.y <= max.y {
+                center.y.clamp(min.y, max.y)
+            } else {
+                (self.min.y + self.max.y) / 2.0
+            },
+        )
+    }
+}
+
+/// The room the camera is confined to, kept when the target leaves all the rooms
 #[derive(Debug, Default, Clone, Component)]
-pub struct CameraSpeed(pub f32);
+pub struct CameraRoom(pub Option<Entity>);
+
+/// The rectangle around the followed point in which the target can move without moving the camera
+#[derive(Debug, Clone, Component)]
+pub struct CameraDeadzone {
+    // The half extents of the rectangle
+    pub half_extents: Vec2,
+    // The point the camera follows, lagging behind the target by at most the half extents
+    focus: Option<Vec2>,
+}
+
+impl CameraDeadzone {
+    /// Create a deadzone with the given half extents
+    pub fn new(half_extents: Vec2) -> Self {
+        Self {
+            half_extents,
+            focus: None,
+        }
+    }
+
+    /// Drag the focus along when the target leaves the rectangle
+    fn follow(&mut self, target: Vec2) -> Vec2 {
+        let focus = self.focus.unwrap_or(target);
+        let focus = focus.clamp(target - self.half_extents, target + self.half_extents);
+        self.focus = Some(focus);
+        focus
+    }
+}
+
+/// Move the camera ahead of the target horizontally, in the direction it is moving or facing
+#[derive(Debug, Clone, Component)]
+pub struct CameraLookAhead {
+    // The distance to look ahead
+    pub distance: f32,
+    // The time in seconds the look-ahead takes to get halfway to its goal
+    pub half_life: f32,
+    // The horizontal speed above which the velocity is used instead of the facing
+    pub velocity_threshold: f32,
+    // The current look-ahead
+    current: f32,
+}
+
+impl CameraLookAhead {
+    /// Create a look-ahead of the given distance
+    pub fn new(distance: f32, half_life: f32) -> Self {
+        Self {
+            distance,
+            half_life,
+            velocity_threshold: 8.0,
+            current: 0.0,
+        }
+    }
+
+    /// Move the look-ahead towards the direction between -1 and 1
+    fn update(&mut self, direction: f32, delta: f32) -> f32 {
+        let goal = direction.clamp(-1.0, 1.0) * self.distance;
+        self.current = damp(self.current, goal, self.half_life, delta);
+        self.current
+    }
+}
+
+/// Only re-center vertically when the target lands
+/// The camera still follows when the target falls below its last ground or rises too far above it
+#[derive(Debug, Clone, Component)]
+pub struct CameraPlatformSnap {
+    // The height above the last ground the target can reach before the camera follows
+    pub max_height: f32,
+    // The height of the last ground the target stood on
+    ground: Option<f32>,
+}
+
+impl CameraPlatformSnap {
+    /// Create a platform snap that follows above the given height
+    pub fn new(max_height: f32) -> Self {
+        Self {
+            max_height,
+            ground: None,
+        }
+    }
+
+    /// The height to follow for the target
+    fn follow(&mut self, target: f32, grounded: bool) -> f32 {
+        if grounded || self.ground.is_none() {
+            self.ground = Some(target);
+        }
+        let ground = self.ground.unwrap_or(target);
+
+        if target < ground {
+            target
+        } else if target > ground + self.max_height {
+            target - self.max_height
+        } else {
+            ground
+        }
+    }
+}
+
+/// Sent to shake the cameras, the trauma is added to the trauma of the cameras
+/// A non-zero direction also kicks the cameras in that direction
+#[derive(Debug, Clone)]
+pub struct CameraShake {
+    pub trauma: f32,
+    pub direction: Vec2,
+}
+
+/// Shakes the camera on top of the follow logic
+/// The shake grows with the square of the trauma, which decays over time
+#[derive(Debug, Clone, Component)]
+pub struct CameraShaker {
+    // The offset at full trauma
+    pub max_offset: Vec2,
+    // The rotation in radians at full trauma
+    pub max_rotation: f32,
+    // The distance of a kick at full trauma
+    pub max_kick: f32,
+    // The speed of the shake noise
+    pub frequency: f32,
+    // The trauma lost per second
+    pub decay: f32,
+    // The speed at which the kicks return to the center
+    pub kick_recovery: f32,
+    // The current trauma between 0 and 1
+    pub trauma: f32,
+    // The current kick offset
+    kick: Vec2,
+    // The time the noise is sampled at
+    time: f32,
+    // The offset and rotation applied last frame, removed before following
+    applied: (Vec2, f32),
+}
+
+impl Default for CameraShaker {
+    fn default() -> Self {
+        Self {
+            max_offset: Vec2::new(8.0, 8.0),
+            max_rotation: 0.05,
+            max_kick: 6.0,
+            frequency: 20.0,
+            decay: 1.5,
+            kick_recovery: 12.0,
+            trauma: 0.0,
+            kick: Vec2::ZERO,
+            time: 0.0,
+            applied: (Vec2::ZERO, 0.0),
+        }
+    }
+}
+
+/// The smoothing of the camera movement towards its goal, separately for each axis
+/// The half-life is the time in seconds it takes to cover half of the distance to the goal
+#[derive(Debug, Default, Clone, Component)]
+pub struct CameraSmoothing {
+    pub half_life: Vec2,
+}
+
+impl CameraSmoothing {
+    /// Create a smoothing with the same half-life on both axes
+    pub fn new(half_life: f32) -> Self {
+        Self {
+            half_life: Vec2::splat(half_life),
+        }
+    }
+
+    /// Move the position towards the goal
+    fn apply(&self, position: Vec2, goal: Vec2, delta: f32) -> Vec2 {
+        Vec2::new(
+            damp(position.x, goal.x, self.half_life.x, delta),
+            damp(position.y, goal.y, self.half_life.y, delta),
+        )
+    }
+}
+
+/// Move the value towards the goal with exponential damping, independent of the frame rate
+/// A half-life of zero or less snaps to the goal
+pub fn damp(current: f32, goal: f32, half_life: f32, delta: f32) -> f32 {
+    if half_life <= 0.0 {
+        return goal;
+    }
+    current + (goal - current) * (1.0 - (-delta / half_life).exp2())
+}
+
+/// How the virtual resolution is scaled to the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolutionScaling {
+    // Show at least the virtual resolution, extending the view on one axis
+    Fit,
+    // Cover the window with the virtual resolution, cropping one axis
+    Fill,
+    // Scale by the largest whole number that fits, with black bars around the view
+    IntegerScale,
+}
+
+/// The resolution the game is designed for, in world units
+#[derive(Debug, Clone)]
+pub struct VirtualResolution {
+    pub width: f32,
+    pub height: f32,
+    pub scaling: ResolutionScaling,
+    // Round the rendered positions of the cameras and sprites to whole world units
+    pub pixel_snap: bool,
+}
+
+impl Default for VirtualResolution {
+    fn default() -> Self {
+        Self {
+            width: 320.0,
+            height: 180.0,
+            scaling: ResolutionScaling::Fit,
+            pixel_snap: true,
+        }
+    }
+}
+
+impl VirtualResolution {
+    /// The projection scale that shows the virtual resolution in a window of the given size
+    pub fn scale(&self, window_width: f32, window_height: f32) -> f32 {
+        let x = self.width / window_width;
+        let y = self.height / window_height;
+        match self.scaling {
+            ResolutionScaling::Fit => x.max(y),
+            ResolutionScaling::Fill => x.min(y),
+            ResolutionScaling::IntegerScale => 1.0 / (1.0 / x.max(y)).floor().max(1.0),
+        }
+    }
+}
+
+/// A black bar around the view when the virtual resolution is letterboxed
+/// The index is the side: left, right, bottom and top
+#[derive(Debug, Clone, Component)]
+struct LetterboxBar(usize);
 
 /// The plugin that handles the game camera
 pub struct GameCameraPlugin;
 
 impl Plugin for GameCameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(update_scaling);
-        app.add_system(update_position);
+        app.init_resource::<VirtualResolution>();
+        app.add_event::<CameraShake>();
+        app.add_event::<CameraTargetLost>();
+
+        app.add_startup_system(spawn_letterbox);
+
+        app.add_system(
+            update_scaling
+                .label("update_scaling")
+                .after("restore_scripted_cameras"),
+        );
+        app.add_system(remove_shake.label("remove_shake"));
+        app.add_system(handle_lost_targets.label("handle_lost_targets"));
+        app.add_system(
+            update_position
+                .label("update_position")
+                .after("update_scaling")
+                .after("remove_shake")
+                .after("handle_lost_targets")
+                .after("restore_scripted_cameras"),
+        );
+        app.add_system(
+            apply_shake
+                .label("apply_shake")
+                .after("update_position")
+                .after("apply_scripted_cameras"),
+        );
+        app.add_system(update_letterbox.after("apply_shake"));
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            snap_to_pixels
+                .label("snap_to_pixels")
+                .after(TransformSystem::TransformPropagate),
+        );
     }
 }
 
-/// Update the camera scaling
+/// Update the camera scaling, zooming out to fit all the targets
 fn update_scaling(
+    time: Res<Time>,
     windows: Res<Windows>,
-    mut cameras: Query<(&Camera, &mut OrthographicProjection)>,
+    resolution: Res<VirtualResolution>,
+    transform_entities: Query<&Transform, Without<Camera>>,
+    mut cameras: Query<(
+        &Camera,
+        &mut OrthographicProjection,
+        Option<&CameraTargets>,
+        Option<&CameraZoom>,
+    )>,
+) {
+    for (camera, mut projection, targets, zoom) in cameras.iter_mut() {
+        // The window of the camera may have been closed
+        let window = match windows.get(camera.window) {
+            Some(window) => window,
+            None => continue,
+        };
+        let base = resolution.scale(window.width(), window.height());
+        let mut scale = base;
+
+        if let Some((_, half_extents)) =
+            targets.and_then(|targets| framing(&targets.0, &transform_entities))
+        {
+            let size = (half_extents + Vec2::splat(FRAMING_MARGIN)) * 2.0;
+            scale = scale
+                .max(size.x / window.width())
+                .max(size.y / window.height());
+        }
+
+        projection.scale = match zoom {
+            Some(zoom) => {
+                let goal = scale.clamp(base * zoom.min, base * zoom.max);
+                damp(projection.scale, goal, zoom.half_life, time.delta_seconds())
+            }
+            None => scale,
+        };
+    }
+}
+
+/// The weighted center of the targets that still exist,
+/// and the half extents around it that fit all of them
+fn framing(
+    targets: &[WeightedTarget],
+    transform_entities: &Query<&Transform, Without<Camera>>,
+) -> Option<(Vec2, Vec2)> {
+    let positions = targets
+        .iter()
+        .filter_map(|target| {
+            transform_entities
+                .get(target.entity)
+                .ok()
+                .map(|transform| (transform.translation.truncate(), target.weight.max(0.0)))
+        })
+        .collect::<Vec<_>>();
+    if positions.is_empty() {
+        return None;
+    }
+
+    // Fall back to the plain average when none of the targets have a weight
+    let total = positions.iter().map(|(_, weight)| weight).sum::<f32>();
+    let center = if total > 0.0 {
+        positions
+            .iter()
+            .map(|(position, weight)| *position * *weight)
+            .fold(Vec2::ZERO, |sum, position| sum + position)
+            / total
+    } else {
+        positions
+            .iter()
+            .map(|(position, _)| *position)
+            .fold(Vec2::ZERO, |sum, position| sum + position)
+            / positions.len() as f32
+    };
+    let half_extents = positions
+        .iter()
+        .map(|(position, _)| (*position - center).abs())
+        .fold(Vec2::ZERO, Vec2::max);
+
+    Some((center, half_extents))
+}
+
+/// Report the camera targets that were despawned and apply the fallback of the camera
+fn handle_lost_targets(
+    mut commands: Commands,
+    mut lost_events: EventWriter<CameraTargetLost>,
+    transform_entities: Query<&Transform, Without<Camera>>,
+    players: Query<(Entity, &PlayerIndex)>,
+    mut cameras: Query<(
+        Entity,
+        Option<&mut CameraTarget>,
+        Option<&mut CameraTargets>,
+        Option<&CameraFallback>,
+    )>,
 ) {
-    for (camera, mut projection) in cameras.iter_mut() {
-        let window = windows.get(camera.window).unwrap();
-        projection.scale = 320.0 / window.width();
+    let next_player = players
+        .iter()
+        .min_by_key(|(_, index)| index.0)
+        .map(|(entity, _)| entity);
+
+    for (camera, target, targets, fallback) in cameras.iter_mut() {
+        let next = match fallback {
+            Some(CameraFallback::NextTarget) => next_player,
+            _ => None,
+        };
+
+        if let Some(mut target) = target {
+            if transform_entities.get(target.0).is_err() {
+                lost_events.send(CameraTargetLost {
+                    camera,
+                    target: target.0,
+                });
+                match next {
+                    Some(next) => target.0 = next,
+                    None => {
+                        commands.entity(camera).remove::<CameraTarget>();
+                    }
+                }
+            }
+        }
+
+        if let Some(mut targets) = targets {
+            let count = targets.0.len();
+            targets.0.retain(|target| {
+                let exists = transform_entities.get(target.entity).is_ok();
+                if !exists {
+                    lost_events.send(CameraTargetLost {
+                        camera,
+                        target: target.entity,
+                    });
+                }
+                exists
+            });
+            if count > 0 && targets.0.is_empty() {
+                if let Some(next) = next {
+                    targets.0.push(WeightedTarget::new(next, 1.0));
+                }
+            }
+        }
     }
 }
 
 /// Move towards the lerping position
+/// The goal is clamped to the room of the target, so moving between rooms pans smoothly
+#[allow(clippy::type_complexity)]
 fn update_position(
     time: Res<Time>,
-    transform_entities: Query<(Entity, &Transform, Without<Camera>)>,
+    transform_entities: Query<&Transform, Without<Camera>>,
+    movement: Query<(Option<&Velocity>, Option<&Facing>, Option<&Contacts>)>,
+    rooms: Query<(Entity, &CameraBounds)>,
     mut cameras: Query<(
         &mut Transform,
-        &CameraTarget,
-        &CameraSpeed,
+        &OrthographicProjection,
+        (
+            Option<&CameraTarget>,
+            Option<&CameraTargets>,
+            Option<&CameraFallback>,
+        ),
+        &CameraSmoothing,
         Option<&CameraOffset>,
+        Option<&mut CameraRoom>,
+        (
+            Option<&mut CameraDeadzone>,
+            Option<&mut CameraLookAhead>,
+            Option<&mut CameraPlatformSnap>,
+        ),
         With<Camera>,
     )>,
 ) {
-    for (mut transform, target, speed, offset, _) in cameras.iter_mut() {
-        let (_, target, _) = transform_entities.get(target.0).unwrap();
-
-        if let Some(offset) = offset {
-            transform.translation.x += (speed.0 * time.delta_seconds())
-                * (target.translation.x + offset.0.x - transform.translation.x);
-            transform.translation.y += (speed.0 * time.delta_seconds())
-                * (target.translation.y + offset.0.y - transform.translation.y);
-        } else {
-            transform.translation.x +=
-                (speed.0 * time.delta_seconds()) * (target.translation.x - transform.translation.x);
-            transform.translation.y +=
-                (speed.0 * time.delta_seconds()) * (target.translation.y - transform.translation.y);
+    for (
+        mut transform,
+        projection,
+        (target, targets, fallback),
+        smoothing,
+        offset,
+        room,
+        (deadzone, look_ahead, platform_snap),
+        _,
+    ) in cameras.iter_mut()
+    {
+        // Follow the center of the targets, or the single target
+        let targets = match (targets, target) {
+            (Some(targets), _) => targets.0.clone(),
+            (None, Some(target)) => vec![WeightedTarget::new(target.0, 1.0)],
+            (None, None) => Vec::new(),
+        };
+        let target = match (framing(&targets, &transform_entities), fallback) {
+            (Some((center, _)), _) => center,
+            (None, Some(CameraFallback::SpawnPoint(point))) => *point,
+            (None, _) => continue,
+        };
+
+        // The targets move in the direction they are moving in, or facing when standing still
+        let mut direction = 0.0;
+        let mut grounded = true;
+        for (velocity, facing, contacts) in targets
+            .iter()
+            .filter_map(|target| movement.get(target.entity).ok())
+        {
+            let velocity = velocity.map_or(Vec2::ZERO, |velocity| velocity.0);
+            direction += match &look_ahead {
+                Some(look_ahead) if velocity.x.abs() > look_ahead.velocity_threshold => {
+                    velocity.x.signum()
+                }
+                _ => facing.map_or(0.0, |facing| facing.0),
+            };
+            grounded &= contacts.map_or(velocity.y == 0.0, |contacts| contacts.down);
+        }
+
+        let mut focus = target;
+        if let Some(mut platform_snap) = platform_snap {
+            focus.y = platform_snap.follow(focus.y, grounded);
+        }
+        if let Some(mut deadzone) = deadzone {
+            focus = deadzone.follow(focus);
+        }
+        if let Some(mut look_ahead) = look_ahead {
+            focus.x += look_ahead.update(direction, time.delta_seconds());
+        }
+        let mut goal = focus + offset.map_or(Vec2::ZERO, |offset| offset.0);
+
+        // Find the smallest room containing the target, or keep the last room
+        let current = rooms
+            .iter()
+            .filter(|(_, bounds)| bounds.contains(target))
+            .min_by(|(_, a), (_, b)| a.area().total_cmp(&b.area()))
+            .map(|(entity, _)| entity);
+        let current = match room {
+            Some(mut room) => {
+                if current.is_some() {
+                    room.0 = current;
+                }
+                room.0
+            }
+            None => current,
+        };
+
+        if let Some((_, bounds)) = current.and_then(|room| rooms.get(room).ok()) {
+            let half_size = Vec2::new(
+                projection.right - projection.left,
+                projection.top - projection.bottom,
+            ) * projection.scale
+                / 2.0;
+            goal = bounds.clamp(goal, half_size);
+        }
+
+        let position =
+            smoothing.apply(transform.translation.truncate(), goal, time.delta_seconds());
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+/// Smooth noise between -1 and 1, a different seed gives an unrelated curve
+fn noise(seed: u32, time: f32) -> f32 {
+    fn hash(seed: u32, x: i32) -> f32 {
+        let mut h = (x as u32).wrapping_mul(0x27d4_eb2d) ^ seed.wrapping_mul(0x1656_67b1);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        h as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    let x = time.floor();
+    let t = time - x;
+    let t = t * t * (3.0 - 2.0 * t);
+    hash(seed, x as i32) * (1.0 - t) + hash(seed, x as i32 + 1) * t
+}
+
+/// Remove the shake of last frame so the follow logic only sees its own position
+fn remove_shake(mut cameras: Query<(&mut Transform, &mut CameraShaker)>) {
+    for (mut transform, mut shaker) in cameras.iter_mut() {
+        let (offset, rotation) = shaker.applied;
+        transform.translation -= offset.extend(0.0);
+        transform.rotation *= Quat::from_rotation_z(-rotation);
+        shaker.applied = (Vec2::ZERO, 0.0);
+    }
+}
+
+/// Add the trauma of the shake events and shake the cameras
+fn apply_shake(
+    time: Res<Time>,
+    mut shake_events: EventReader<CameraShake>,
+    mut cameras: Query<(&mut Transform, &mut CameraShaker)>,
+) {
+    let shakes = shake_events.iter().collect::<Vec<_>>();
+
+    for (mut transform, mut shaker) in cameras.iter_mut() {
+        let shaker = &mut *shaker;
+        for shake in shakes.iter() {
+            shaker.trauma = (shaker.trauma + shake.trauma).clamp(0.0, 1.0);
+            shaker.kick += shake.direction.normalize_or_zero() * shake.trauma * shaker.max_kick;
+        }
+
+        let delta = time.delta_seconds();
+        shaker.trauma = (shaker.trauma - shaker.decay * delta).max(0.0);
+        shaker.kick *= (-shaker.kick_recovery * delta).exp();
+        shaker.time += delta * shaker.frequency;
+
+        let shake = shaker.trauma * shaker.trauma;
+        let offset = Vec2::new(
+            shaker.max_offset.x * shake * noise(0, shaker.time),
+            shaker.max_offset.y * shake * noise(1, shaker.time),
+        ) + shaker.kick;
+        let rotation = shaker.max_rotation * shake * noise(2, shaker.time);
+
+        transform.translation += offset.extend(0.0);
+        transform.rotation *= Quat::from_rotation_z(rotation);
+        shaker.applied = (offset, rotation);
+    }
+}
+
+/// Spawn the letterbox bars, they are hidden unless the scaling is letterboxed
+fn spawn_letterbox(mut commands: Commands) {
+    for side in 0..4 {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::BLACK,
+                    custom_size: Some(Vec2::ZERO),
+                    ..Default::default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(LetterboxBar(side));
+    }
+}
+
+/// Cover the parts of the view outside of the virtual resolution with the letterbox bars
+fn update_letterbox(
+    windows: Res<Windows>,
+    resolution: Res<VirtualResolution>,
+    cameras: Query<(&Camera, &Transform, &OrthographicProjection), Without<LetterboxBar>>,
+    mut bars: Query<(&LetterboxBar, &mut Transform, &mut Sprite, &mut Visibility)>,
+) {
+    let letterboxed = resolution.scaling == ResolutionScaling::IntegerScale;
+    let camera = cameras
+        .iter()
+        .find(|(camera, _, _)| camera.name.as_deref() == Some(CAMERA_2D));
+    let (camera, camera_transform, projection) = match (camera, letterboxed) {
+        (Some(camera), true) => camera,
+        _ => {
+            for (_, _, _, mut visibility) in bars.iter_mut() {
+                visibility.is_visible = false;
+            }
+            return;
+        }
+    };
+    let window = match windows.get(camera.window) {
+        Some(window) => window,
+        None => return,
+    };
+
+    // The virtual area grows with the zoom of the camera
+    let zoom = projection.scale / resolution.scale(window.width(), window.height());
+    let inner = Vec2::new(resolution.width, resolution.height) * zoom / 2.0;
+    let outer = Vec2::new(window.width(), window.height()) * projection.scale / 2.0;
+    let thickness = (outer - inner).max(Vec2::ZERO);
+
+    for (bar, mut transform, mut sprite, mut visibility) in bars.iter_mut() {
+        let (offset, size) = match bar.0 {
+            0 => (
+                Vec2::new(-(inner.x + thickness.x / 2.0), 0.0),
+                Vec2::new(thickness.x, outer.y * 2.0),
+            ),
+            1 => (
+                Vec2::new(inner.x + thickness.x / 2.0, 0.0),
+                Vec2::new(thickness.x, outer.y * 2.0),
+            ),
+            2 => (
+                Vec2::new(0.0, -(inner.y + thickness.y / 2.0)),
+                Vec2::new(outer.x * 2.0, thickness.y),
+            ),
+            _ => (
+                Vec2::new(0.0, inner.y + thickness.y / 2.0),
+                Vec2::new(outer.x * 2.0, thickness.y),
+            ),
+        };
+
+        // Draw the bars just in front of the camera, on top of everything else
+        transform.translation = (camera_transform.translation.truncate() + offset)
+            .extend(camera_transform.translation.z - 1.0);
+        sprite.custom_size = Some(size);
+        visibility.is_visible = size.x > 0.0 && size.y > 0.0;
+    }
+}
+
+/// Round the rendered positions of the cameras and sprites to whole world units
+/// Only the global transforms are snapped, so the movement and physics keep their precision
+fn snap_to_pixels(
+    resolution: Res<VirtualResolution>,
+    mut transforms: Query<&mut GlobalTransform, Or<(With<Sprite>, With<Camera>)>>,
+) {
+    if !resolution.pixel_snap {
+        return;
+    }
+    for mut transform in transforms.iter_mut() {
+        transform.translation.x = transform.translation.x.round();
+        transform.translation.y = transform.translation.y.round();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Follow a target that jumps away for a second at the frame rate
+    fn follow(fps: u32) -> Vec2 {
+        let delta = 1.0 / fps as f32;
+        let target = Vec2::new(100.0, 50.0);
+        let smoothing = CameraSmoothing::new(0.1);
+        let mut deadzone = CameraDeadzone::new(Vec2::new(16.0, 8.0));
+        let mut look_ahead = CameraLookAhead::new(32.0, 0.5);
+        deadzone.follow(Vec2::ZERO);
+
+        let mut position = Vec2::ZERO;
+        for _ in 0..fps {
+            let mut focus = deadzone.follow(target);
+            focus.x += look_ahead.update(1.0, delta);
+            position = smoothing.apply(position, focus, delta);
         }
+        position
+    }
+
+    #[test]
+    fn follows_the_same_at_any_frame_rate() {
+        let slow = follow(30);
+        let fast = follow(240);
+        assert!(
+            (slow - fast).abs().max_element() < 0.5,
+            "{} at 30 fps and {} at 240 fps",
+            slow,
+            fast
+        );
+    }
+
+    #[test]
+    fn long_frame_does_not_overshoot() {
+        assert!(damp(0.0, 100.0, 0.25, 10.0) <= 100.0);
     }
 }
@@ -0,0 +1,362 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::Deserialize;
+
+use crate::prelude::{Collider, GameState, PlayerIndex, VirtualResolution};
+
+/// The easing of the movement towards a keyframe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Self::EaseInOut
+    }
+}
+
+impl Easing {
+    /// Ease the progress between 0 and 1
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A point on a camera path
+#[derive(Debug, Clone, Deserialize)]
+pub struct CameraKeyframe {
+    // The time in seconds from the start of the path the camera reaches the keyframe
+    pub time: f32,
+    // The position of the camera
+    pub position: Vec2,
+    // The zoom of the camera relative to the default view, higher values zoom out
+    #[serde(default = "default_zoom")]
+    pub zoom: f32,
+    // The easing of the movement from the previous keyframe
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+fn default_zoom() -> f32 {
+    1.0
+}
+
+/// A spline through timed keyframes for the camera to follow
+/// Two keyframes at the same position hold the camera in place
+/// Loaded from `.camera.ron` files
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "1ca391eb-e662-4954-b3e8-da854fe41b87"]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    /// The time in seconds until the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// The position and zoom on the path at the time
+    pub fn sample(&self, time: f32) -> Option<(Vec2, f32)> {
+        let keyframes = &self.keyframes;
+        let first = keyframes.first()?;
+        let last = keyframes.last()?;
+        if time <= first.time {
+            return Some((first.position, first.zoom));
+        }
+        if time >= last.time {
+            return Some((last.position, last.zoom));
+        }
+
+        // Find the segment the time is in
+        let index = keyframes
+            .windows(2)
+            .position(|segment| time < segment[1].time)
+            .unwrap_or(keyframes.len() - 2);
+        let from = &keyframes[index];
+        let to = &keyframes[index + 1];
+        let length = to.time - from.time;
+        let t = if length > 0.0 {
+            to.easing.apply((time - from.time) / length)
+        } else {
+            1.0
+        };
+
+        // Catmull-Rom through the neighbouring keyframes, so the camera doesn't stop at each keyframe
+        let before = keyframes
+            .get(index.wrapping_sub(1))
+            .unwrap_or(from)
+            .position;
+        let after = keyframes.get(index + 2).unwrap_or(to).position;
+        let position = catmull_rom(before, from.position, to.position, after, t);
+
+        Some((position, from.zoom + (to.zoom - from.zoom) * t))
+    }
+}
+
+/// A point on the Catmull-Rom spline between p1 and p2
+fn catmull_rom(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// Loads [`CameraPath`]s from ron files
+#[derive(Debug, Default)]
+pub struct CameraPathLoader;
+
+impl AssetLoader for CameraPathLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let path = ron::de::from_bytes::<CameraPath>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(path));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["camera.ron"]
+    }
+}
+
+/// Takes control of the camera from the follow logic to move it along a path
+/// The follow logic keeps running underneath, so the camera blends back to where it would be
+#[derive(Debug, Clone, Component)]
+pub struct ScriptedCamera {
+    // The path to move along
+    pub path: CameraPath,
+    // The time in seconds to blend from and back to the follow logic
+    pub blend: f32,
+    // Give the camera back when the path ends, otherwise it holds the last keyframe until released
+    pub release_at_end: bool,
+    // The flag for if the camera plays the cutscene, which ends when the camera is handed back
+    pub is_cutscene: bool,
+    // The time on the path
+    time: f32,
+    // The time since the camera was released
+    released: Option<f32>,
+    // The position and scale of the follow logic, restored before following
+    follow: Option<(Vec3, f32)>,
+}
+
+impl ScriptedCamera {
+    /// Take control of the camera with the path, blending in over the blend time
+    pub fn new(path: CameraPath, blend: f32) -> Self {
+        Self {
+            path,
+            blend,
+            release_at_end: true,
+            is_cutscene: false,
+            time: 0.0,
+            released: None,
+            follow: None,
+        }
+    }
+
+    /// Start handing the camera back to the follow logic
+    pub fn release(&mut self) {
+        if self.released.is_none() {
+            self.released = Some(0.0);
+        }
+    }
+
+    /// How much of the camera is controlled by the path, between 0 and 1
+    fn weight(&self) -> f32 {
+        if self.blend <= 0.0 {
+            return if self.is_finished() { 0.0 } else { 1.0 };
+        }
+        let blend_in = self.time / self.blend;
+        let blend_out = 1.0 - self.released.unwrap_or(0.0) / self.blend;
+        Easing::EaseInOut.apply(blend_in.min(blend_out))
+    }
+
+    /// Check if the camera is fully handed back
+    pub fn is_finished(&self) -> bool {
+        self.released
+            .map_or(false, |released| released >= self.blend)
+    }
+}
+
+/// Moves the cameras along a path when a player enters the area
+/// Placed in the level, the path is loaded from the asset path
+#[derive(Debug, Clone, Component)]
+pub struct CameraPathTrigger {
+    // The path the cameras move along
+    pub path: Handle<CameraPath>,
+    // The half extents of the area around the trigger
+    pub half_extents: Vec2,
+    // The time in seconds to blend from and back to the follow logic
+    pub blend: f32,
+    // Play the path as a cutscene, which pauses the gameplay
+    pub cutscene: bool,
+    // The flag for if the trigger already played
+    pub triggered: bool,
+}
+
+/// The plugin for the scripted camera paths
+pub struct CameraPathPlugin;
+
+impl Plugin for CameraPathPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<CameraPath>();
+        app.init_asset_loader::<CameraPathLoader>();
+
+        app.add_system(
+            restore_scripted_cameras
+                .label("restore_scripted_cameras")
+                .after("remove_shake"),
+        );
+        app.add_system(
+            apply_scripted_cameras
+                .label("apply_scripted_cameras")
+                .after("update_position")
+                .after("update_scaling"),
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay).with_system(handle_path_triggers),
+        );
+    }
+}
+
+/// Put the cameras back where the follow logic left them
+fn restore_scripted_cameras(
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection, &ScriptedCamera)>,
+) {
+    for (mut transform, mut projection, scripted) in cameras.iter_mut() {
+        if let Some((translation, scale)) = scripted.follow {
+            transform.translation = translation;
+            projection.scale = scale;
+        }
+    }
+}
+
+/// Move the cameras along their paths, blended with the follow logic
+fn apply_scripted_cameras(
+    mut commands: Commands,
+    time: Res<Time>,
+    windows: Res<Windows>,
+    resolution: Res<VirtualResolution>,
+    mut state: ResMut<State<GameState>>,
+    mut cameras: Query<(
+        Entity,
+        &Camera,
+        &mut Transform,
+        &mut OrthographicProjection,
+        &mut ScriptedCamera,
+    )>,
+) {
+    for (entity, camera, mut transform, mut projection, mut scripted) in cameras.iter_mut() {
+        let scripted = &mut *scripted;
+        scripted.follow = Some((transform.translation, projection.scale));
+
+        // Advance along the path, or towards the follow logic when released
+        let delta = time.delta_seconds();
+        match scripted.released.as_mut() {
+            Some(released) => *released += delta,
+            None => scripted.time += delta,
+        }
+        if scripted.release_at_end && scripted.time >= scripted.path.duration() {
+            scripted.release();
+        }
+
+        if scripted.is_finished() {
+            commands.entity(entity).remove::<ScriptedCamera>();
+            // End the cutscene the camera was playing, other cameras leave it running
+            if scripted.is_cutscene && *state.current() == GameState::Cutscene {
+                // A transition may already be queued this frame
+                let _ = state.pop();
+            }
+            continue;
+        }
+
+        let (position, zoom) = match scripted.path.sample(scripted.time) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let scale = match windows.get(camera.window) {
+            Some(window) => resolution.scale(window.width(), window.height()) * zoom,
+            None => projection.scale,
+        };
+
+        let weight = scripted.weight();
+        let follow = transform.translation.truncate();
+        let position = follow + (position - follow) * weight;
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        projection.scale += (scale - projection.scale) * weight;
+    }
+}
+
+/// Start the paths of the triggers the players enter
+fn handle_path_triggers(
+    mut commands: Commands,
+    paths: Res<Assets<CameraPath>>,
+    mut state: ResMut<State<GameState>>,
+    mut triggers: Query<(&Transform, &mut CameraPathTrigger)>,
+    players: Query<(&Transform, &Collider), With<PlayerIndex>>,
+    cameras: Query<Entity, (With<Camera>, Without<ScriptedCamera>)>,
+) {
+    // The cameras started this frame, the query only sees them from the next frame
+    let mut started = Vec::new();
+    for (transform, mut trigger) in triggers.iter_mut() {
+        if trigger.triggered {
+            continue;
+        }
+
+        let entered = players.iter().any(|(player, collider)| {
+            let distance = (player.translation - transform.translation)
+                .truncate()
+                .abs();
+            let reach = trigger.half_extents + collider.half_extents;
+            distance.x < reach.x && distance.y < reach.y
+        });
+        if !entered {
+            continue;
+        }
+
+        // Wait for the path to be loaded
+        let path = match paths.get(&trigger.path) {
+            Some(path) => path,
+            None => continue,
+        };
+        trigger.triggered = true;
+
+        let mut is_cutscene = trigger.cutscene;
+        for camera in cameras.iter() {
+            if started.contains(&camera) {
+                continue;
+            }
+            started.push(camera);
+
+            // The first camera owns the cutscene
+            let mut scripted = ScriptedCamera::new(path.clone(), trigger.blend);
+            scripted.is_cutscene = is_cutscene;
+            if is_cutscene {
+                is_cutscene = false;
+                // A transition may already be queued this frame
+                let _ = state.push(GameState::Cutscene);
+            }
+            commands.entity(camera).insert(scripted);
+        }
+    }
+}
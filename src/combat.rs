@@ -0,0 +1,111 @@
+use bevy::prelude::*;
+
+use crate::prelude::GameState;
+
+/// The direction an entity is facing, -1 for left and 1 for right
+#[derive(Debug, Clone, Component)]
+pub struct Facing(pub f32);
+
+impl Default for Facing {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// A box that hits the hurtboxes it overlaps
+/// Follows its owner at the given offset
+#[derive(Debug, Clone, Component)]
+pub struct Hitbox {
+    // The entity that owns the hitbox
+    pub owner: Entity,
+    // The offset from the owner
+    pub offset: Vec2,
+    // The half extents of the box
+    pub half_extents: Vec2,
+    // The damage dealt on a hit
+    pub damage: f32,
+    // The knockback applied on a hit
+    pub knockback: Vec2,
+    // The entities that were already hit, each entity is only hit once per hitbox
+    pub hits: Vec<Entity>,
+}
+
+/// A box that can be hit by hitboxes, marks an entity as damageable
+#[derive(Debug, Clone, Component)]
+pub struct Hurtbox {
+    pub half_extents: Vec2,
+}
+
+/// Sent when a hitbox hits a hurtbox
+#[derive(Debug, Clone)]
+pub struct HitEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub damage: f32,
+    pub knockback: Vec2,
+}
+
+/// The plugin for the hitboxes and hurtboxes
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<HitEvent>();
+
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(
+                    update_hitboxes
+                        .label("update_hitboxes")
+                        .after("handle_attack"),
+                )
+                .with_system(detect_hits.label("detect_hits").after("update_hitboxes")),
+        );
+    }
+}
+
+/// Move the hitboxes to their owners, and remove the hitboxes of despawned owners
+fn update_hitboxes(
+    mut commands: Commands,
+    mut hitboxes: Query<(Entity, &mut Transform, &Hitbox)>,
+    owners: Query<&Transform, Without<Hitbox>>,
+) {
+    for (entity, mut transform, hitbox) in hitboxes.iter_mut() {
+        match owners.get(hitbox.owner) {
+            Ok(owner) => {
+                transform.translation = owner.translation + hitbox.offset.extend(0.0);
+            }
+            Err(_) => commands.entity(entity).despawn(),
+        }
+    }
+}
+
+/// Check the hitboxes against the hurtboxes and report the hits
+fn detect_hits(
+    mut hit_events: EventWriter<HitEvent>,
+    mut hitboxes: Query<(&Transform, &mut Hitbox)>,
+    hurtboxes: Query<(Entity, &Transform, &Hurtbox)>,
+) {
+    for (transform, mut hitbox) in hitboxes.iter_mut() {
+        for (target, target_transform, hurtbox) in hurtboxes.iter() {
+            // Don't hit the owner or the same entity twice
+            if target == hitbox.owner || hitbox.hits.contains(&target) {
+                continue;
+            }
+
+            let distance = (target_transform.translation - transform.translation)
+                .truncate()
+                .abs();
+            let reach = hitbox.half_extents + hurtbox.half_extents;
+            if distance.x < reach.x && distance.y < reach.y {
+                hitbox.hits.push(target);
+                hit_events.send(HitEvent {
+                    attacker: hitbox.owner,
+                    target,
+                    damage: hitbox.damage,
+                    knockback: hitbox.knockback,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use bevy::prelude::*;
+
+use crate::prelude::{
+    ActionState, CameraTargets, Collider, GameState, GamepadAssignment, Health, Hurtbox,
+    Invulnerability, KeyboardAssignment, PlayerBundle, PlayerControllerState, PlayerIndex,
+    PlayerInputState, Respawn, WeightedTarget,
+};
+
+/// The maximum amount of local players
+pub const MAX_PLAYERS: usize = 4;
+
+/// The colors the players are tinted with, by player index
+const PLAYER_COLORS: [Color; MAX_PLAYERS] =
+    [Color::WHITE, Color::SALMON, Color::AQUAMARINE, Color::GOLD];
+
+/// The color of an empty slot on the join screen
+const EMPTY_SLOT_COLOR: Color = Color::DARK_GRAY;
+
+/// The device a local player plays with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlayerDevice {
+    Keyboard,
+    Gamepad(Gamepad),
+}
+
+/// The devices of the players that joined, in player index order
+#[derive(Debug, Default)]
+pub struct JoinedPlayers(pub Vec<PlayerDevice>);
+
+impl JoinedPlayers {
+    /// Join with the device if it has not joined yet and there is a free slot
+    pub fn join(&mut self, device: PlayerDevice) {
+        if !self.0.contains(&device) && self.0.len() < MAX_PLAYERS {
+            info!("Player {} joined with {:?}", self.0.len() + 1, device);
+            self.0.push(device);
+        }
+    }
+
+    /// Leave with the device, the players after it move up a slot
+    pub fn leave(&mut self, device: PlayerDevice) {
+        self.0.retain(|joined| *joined != device);
+    }
+}
+
+/// A slot on the join screen
+#[derive(Debug, Clone, Component)]
+struct JoinSlot(usize);
+
+/// The plugin for local multiplayer
+/// Players join on the join screen and are spawned when gameplay starts
+pub struct CoopPlugin;
+
+impl Plugin for CoopPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<JoinedPlayers>();
+
+        app.add_system_set(SystemSet::on_enter(GameState::Join).with_system(setup_join_screen));
+        app.add_system_set(
+            SystemSet::on_update(GameState::Join)
+                .with_system(handle_join.label("handle_join"))
+                .with_system(update_join_slots.after("handle_join")),
+        );
+        app.add_system_set(
+            SystemSet::on_exit(GameState::Join)
+                .with_system(cleanup_join_screen)
+                .with_system(spawn_players),
+        );
+    }
+}
+
+/// Spawn the slots of the join screen
+fn setup_join_screen(mut commands: Commands) {
+    for index in 0..MAX_PLAYERS {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: EMPTY_SLOT_COLOR,
+                    custom_size: Some(Vec2::new(32.0, 32.0)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(
+                    (index as f32 - (MAX_PLAYERS - 1) as f32 / 2.0) * 48.0,
+                    0.0,
+                    0.0,
+                ),
+                ..Default::default()
+            })
+            .insert(JoinSlot(index));
+    }
+}
+
+/// Join with any button, leave with escape or east and start with enter or start
+fn handle_join(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Input<GamepadButton>>,
+    mut joined: ResMut<JoinedPlayers>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let mut start = false;
+
+    // Check the keyboard
+    if keys.just_pressed(KeyCode::Escape) {
+        joined.leave(PlayerDevice::Keyboard);
+    } else if keys.get_just_pressed().len() > 0 {
+        joined.join(PlayerDevice::Keyboard);
+        start |= keys.just_pressed(KeyCode::Return);
+    }
+
+    // Check the gamepads
+    for button in gamepads.get_just_pressed() {
+        let device = PlayerDevice::Gamepad(button.0);
+        match button.1 {
+            GamepadButtonType::East => joined.leave(device),
+            GamepadButtonType::Start => {
+                joined.join(device);
+                start = true;
+            }
+            _ => joined.join(device),
+        }
+    }
+
+    if start && !joined.0.is_empty() {
+        // A transition may already be queued this frame
+        let _ = state.set(GameState::Gameplay);
+    }
+}
+
+/// Color the slots of the players that joined
+fn update_join_slots(joined: Res<JoinedPlayers>, mut slots: Query<(&JoinSlot, &mut Sprite)>) {
+    for (slot, mut sprite) in slots.iter_mut() {
+        sprite.color = if slot.0 < joined.0.len() {
+            PLAYER_COLORS[slot.0]
+        } else {
+            EMPTY_SLOT_COLOR
+        };
+    }
+}
+
+/// Remove the slots of the join screen
+fn cleanup_join_screen(mut commands: Commands, slots: Query<Entity, With<JoinSlot>>) {
+    for entity in slots.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Spawn a player for every device that joined and have the cameras keep them in view
+fn spawn_players(
+    asset_server: Res<AssetServer>,
+    joined: Res<JoinedPlayers>,
+    mut cameras: Query<&mut CameraTargets>,
+    mut commands: Commands,
+) {
+    let mut players = Vec::new();
+    for (index, device) in joined.0.iter().enumerate() {
+        let mut player = commands.spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: PLAYER_COLORS[index],
+                custom_size: Some(Vec2::new(24.0, 24.0)),
+                ..Default::default()
+            },
+            texture: asset_server.load("sprites/bobert.png"),
+            ..Default::default()
+        });
+        let spawn = Vec2::new(index as f32 * 24.0, 0.0);
+        player.insert_bundle(PlayerBundle {
+            index: PlayerIndex(index),
+            // The buttons that started the game are still held
+            actions: ActionState::ignoring_held(),
+            velocity: Vec2::new(0f32, 0f32).into(),
+            friction: Vec2::new(0f32, 0f32).into(),
+            collider: Collider {
+                half_extents: Vec2::new(12f32, 12f32),
+            },
+            health: Health::new(10f32),
+            invulnerability: Invulnerability::new(1f32),
+            hurtbox: Hurtbox {
+                half_extents: Vec2::new(12f32, 12f32),
+            },
+            respawn: Respawn { point: spawn },
+            ..Default::default()
+        });
+        player.insert(Transform {
+            translation: spawn.extend(0.0),
+            ..Default::default()
+        });
+
+        // Bind the device to the player
+        match device {
+            PlayerDevice::Keyboard => {
+                player.insert(KeyboardAssignment);
+            }
+            PlayerDevice::Gamepad(gamepad) => {
+                player.insert(GamepadAssignment {
+                    gamepad: Some(*gamepad),
+                    connected: true,
+                });
+                player.insert(PlayerInputState {
+                    controller: PlayerControllerState::Gamepad(gamepad.0),
+                    ..Default::default()
+                });
+            }
+        }
+        players.push(WeightedTarget::new(player.id(), 1.0));
+    }
+
+    for mut targets in cameras.iter_mut() {
+        targets.0 = players.clone();
+    }
+}
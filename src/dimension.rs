@@ -0,0 +1,268 @@
+use bevy::{prelude::*, render::camera::CAMERA_2D, transform::TransformSystem};
+
+use crate::prelude::{
+    Action, ActionState, Collider, Easing, GameState, GameTime, PhysicsBody, PhysicsConfig,
+    LANE_TOLERANCE,
+};
+
+/// The draw depth an entity moves back by per unit of depth lane
+/// Kept small so the entities stay in the depth range of the 2d camera
+const DEPTH_DRAW_ORDER: f32 = 0.0001;
+
+/// The depth of an entity, positive values are further away from the camera
+/// Moves the entity in the angled view and separates the collisions in both views
+#[derive(Debug, Default, Clone, Copy, PartialEq, Component)]
+pub struct DepthLane(pub f32);
+
+/// Lets an entity move between the depth lanes while the view is angled
+/// The entity collides in the new lane at once and is only drawn moving there
+#[derive(Debug, Default, Clone, Component)]
+pub struct LaneMover {
+    // The depth the entity is drawn at relative to its lane, until it reaches the lane
+    pub offset: f32,
+}
+
+/// The view the camera shows the world from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    // The flat 2d view from the side
+    Side,
+    // The view turned around the vertical axis, showing the depth lanes
+    Angled,
+}
+
+/// The view of the world and the flip between the side and angled view
+#[derive(Debug, Clone)]
+pub struct DimensionView {
+    // The view that is flipped towards
+    pub mode: ViewMode,
+    // The angle in radians the angled view is turned by
+    pub angle: f32,
+    // How much the depth lanes rise on the screen in the angled view, per unit of depth
+    pub elevation: f32,
+    // The time in seconds a flip takes
+    pub duration: f32,
+    // Only let the bodies in the same lane collide
+    pub lane_collisions: bool,
+    // The depth between two lanes
+    pub lane_depth: f32,
+    // The amount of lanes, starting at a depth of 0
+    pub lanes: usize,
+    // The time in seconds it takes to move to the next lane
+    pub lane_change_duration: f32,
+    // The progress of the flip, 0 is the side view and 1 the angled view
+    progress: f32,
+}
+
+impl Default for DimensionView {
+    fn default() -> Self {
+        Self {
+            mode: ViewMode::Side,
+            angle: 50f32.to_radians(),
+            elevation: 0.3,
+            duration: 0.6,
+            lane_collisions: true,
+            lane_depth: 48.0,
+            lanes: 2,
+            lane_change_duration: 0.2,
+            progress: 0.0,
+        }
+    }
+}
+
+impl DimensionView {
+    /// Flip to the other view
+    pub fn flip(&mut self) {
+        self.mode = match self.mode {
+            ViewMode::Side => ViewMode::Angled,
+            ViewMode::Angled => ViewMode::Side,
+        };
+    }
+
+    /// Check if the view is fully in the side view
+    pub fn is_flat(&self) -> bool {
+        self.mode == ViewMode::Side && self.progress <= 0.0
+    }
+
+    /// Check if the view is fully in the angled view
+    pub fn is_angled(&self) -> bool {
+        self.mode == ViewMode::Angled && self.progress >= 1.0
+    }
+
+    /// The current angle of the view, eased over the flip
+    pub fn current_angle(&self) -> f32 {
+        self.angle * Easing::EaseInOut.apply(self.progress)
+    }
+}
+
+/// The plugin for flipping between the side and angled view
+pub struct DimensionPlugin;
+
+impl Plugin for DimensionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DimensionView>();
+
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(
+                    handle_flip
+                        .label("handle_flip")
+                        .after("update_action_states")
+                        .after("replay_inputs"),
+                )
+                .with_system(animate_flip.label("animate_flip").after("handle_flip"))
+                .with_system(update_lane_collisions.after("animate_flip"))
+                .with_system(
+                    handle_lane_change
+                        .label("handle_lane_change")
+                        .after("update_action_states")
+                        .after("replay_inputs"),
+                )
+                .with_system(move_lanes.after("handle_lane_change")),
+        );
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            project_depth
+                .after(TransformSystem::TransformPropagate)
+                .before("snap_to_pixels"),
+        );
+    }
+}
+
+/// Flip the view when a player presses flip
+fn handle_flip(mut view: ResMut<DimensionView>, players: Query<&ActionState>) {
+    if players
+        .iter()
+        .any(|actions| actions.just_pressed(Action::Flip))
+    {
+        view.flip();
+    }
+}
+
+/// Move to the lane in front or behind when a player presses a lane change in the angled view
+/// The move is refused when the lane is outside of the lanes or blocked where the player is
+fn handle_lane_change(
+    view: Res<DimensionView>,
+    mut players: Query<(
+        &ActionState,
+        &Transform,
+        &Collider,
+        &mut DepthLane,
+        &mut LaneMover,
+    )>,
+    solids: Query<
+        (&Transform, &Collider, Option<&DepthLane>),
+        (With<PhysicsBody>, Without<LaneMover>),
+    >,
+) {
+    if !view.is_angled() || view.lane_depth <= 0.0 {
+        return;
+    }
+
+    for (actions, transform, collider, mut lane, mut mover) in players.iter_mut() {
+        if mover.offset != 0.0 {
+            continue;
+        }
+        let direction = if actions.just_pressed(Action::LaneBack) {
+            1.0
+        } else if actions.just_pressed(Action::LaneFront) {
+            -1.0
+        } else {
+            continue;
+        };
+
+        let index = (lane.0 / view.lane_depth).round() + direction;
+        if index < 0.0 || index >= view.lanes as f32 {
+            continue;
+        }
+        let target = index * view.lane_depth;
+
+        let position = transform.translation.truncate();
+        let blocked = solids.iter().any(|(solid, solid_collider, solid_lane)| {
+            let solid_lane = solid_lane.map_or(0.0, |lane| lane.0);
+            let distance = (solid.translation.truncate() - position).abs();
+            let reach = collider.half_extents + solid_collider.half_extents;
+            (solid_lane - target).abs() < LANE_TOLERANCE
+                && distance.x < reach.x
+                && distance.y < reach.y
+        });
+        if !blocked {
+            mover.offset = lane.0 - target;
+            lane.0 = target;
+        }
+    }
+}
+
+/// Move the entities that changed lanes towards their lane
+fn move_lanes(time: Res<GameTime>, view: Res<DimensionView>, mut movers: Query<&mut LaneMover>) {
+    let step = if view.lane_change_duration > 0.0 {
+        view.lane_depth / view.lane_change_duration * time.delta_seconds()
+    } else {
+        f32::INFINITY
+    };
+
+    for mut mover in movers.iter_mut() {
+        if mover.offset.abs() <= step {
+            mover.offset = 0.0;
+        } else {
+            mover.offset -= mover.offset.signum() * step;
+        }
+    }
+}
+
+/// Turn the view towards the mode it is flipping to
+fn animate_flip(time: Res<GameTime>, mut view: ResMut<DimensionView>) {
+    let goal = match view.mode {
+        ViewMode::Side => 0.0,
+        ViewMode::Angled => 1.0,
+    };
+    let step = if view.duration > 0.0 {
+        time.delta_seconds() / view.duration
+    } else {
+        1.0
+    };
+
+    view.progress = if goal > view.progress {
+        (view.progress + step).min(goal)
+    } else {
+        (view.progress - step).max(goal)
+    };
+}
+
+/// Only collide within the lanes
+fn update_lane_collisions(view: Res<DimensionView>, mut physics: ResMut<PhysicsConfig>) {
+    if physics.lane_collisions != view.lane_collisions {
+        physics.lane_collisions = view.lane_collisions;
+    }
+}
+
+/// Draw the entities with a depth lane as seen from the turned view
+/// The world turns around the center of the game camera, which also narrows the entities,
+/// and only the global transforms are changed so the movement and physics stay 2d
+fn project_depth(
+    view: Res<DimensionView>,
+    cameras: Query<(&Camera, &GlobalTransform), Without<DepthLane>>,
+    mut entities: Query<(&DepthLane, Option<&LaneMover>, &mut GlobalTransform)>,
+) {
+    let angle = view.current_angle();
+    if angle <= 0.0 {
+        return;
+    }
+    let center = match cameras
+        .iter()
+        .find(|(camera, _)| camera.name.as_deref() == Some(CAMERA_2D))
+    {
+        Some((_, transform)) => transform.translation.x,
+        None => return,
+    };
+
+    let (sin, cos) = angle.sin_cos();
+    for (lane, mover, mut transform) in entities.iter_mut() {
+        let depth = lane.0 + mover.map_or(0.0, |mover| mover.offset);
+        let translation = &mut transform.translation;
+        translation.x = center + (translation.x - center) * cos + depth * sin;
+        translation.y += depth * sin * view.elevation;
+        translation.z -= depth * sin * DEPTH_DRAW_ORDER;
+        transform.scale.x *= cos;
+    }
+}
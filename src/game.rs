@@ -1,16 +1,40 @@
 use crate::prelude::*;
-use bevy::prelude::*;
+use bevy::{asset::AssetServerSettings, prelude::*};
 
 /// The state of the game
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum GameState {
     Startup,
     Titlescreen,
+    Join,
     Gameplay,
     Menu,
     Cutscene,
 }
 
+/// The id of the level that is currently loaded
+#[derive(Debug, Default, Clone)]
+pub struct LevelId(pub String);
+
+/// The time the gameplay steps with
+/// Follows [`Time`], except when replaying, where the frame times come from the recording
+#[derive(Debug, Default, Clone)]
+pub struct GameTime {
+    delta: f32,
+}
+
+impl GameTime {
+    /// The time in seconds the gameplay advances by this frame
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta
+    }
+
+    /// Override the time the gameplay advances by this frame
+    pub fn set_delta_seconds(&mut self, delta: f32) {
+        self.delta = delta;
+    }
+}
+
 /// The main plugin that builds the game
 pub struct GamePlugin;
 
@@ -21,6 +45,8 @@ impl Plugin for GamePlugin {
 
         // Insert the resources
         app.insert_resource(ClearColor(Color::BLACK));
+        app.init_resource::<LevelId>();
+        app.init_resource::<GameTime>();
         app.insert_resource(WindowDescriptor {
             title: "Highground".into(),
             width: 1280.0,
@@ -28,14 +54,35 @@ impl Plugin for GamePlugin {
             ..Default::default()
         });
 
+        // Watch the assets for changes in debug builds so tuning can be hot-reloaded
+        app.insert_resource(AssetServerSettings {
+            watch_for_changes: cfg!(debug_assertions),
+            ..Default::default()
+        });
+
         // Add the plugins
         app.add_plugins(DefaultPlugins);
         app.add_plugin(GameCameraPlugin);
+        app.add_plugin(CameraPathPlugin);
+        app.add_plugin(ParallaxPlugin);
+        app.add_plugin(DimensionPlugin);
         app.add_plugin(PhysicsPlugin);
+        app.add_plugin(ActionInputPlugin);
+        app.add_plugin(InputBufferPlugin);
+        app.add_plugin(TuningPlugin);
         app.add_plugin(PlayerPlugin);
+        app.add_plugin(CombatPlugin);
+        app.add_plugin(HealthPlugin);
+        app.add_plugin(CoopPlugin);
+        app.add_plugin(ReplayPlugin);
 
         // Add the systems
         app.add_system_set(SystemSet::on_enter(GameState::Startup).with_system(setup));
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            update_game_time.label("update_game_time"),
+        );
+        app.add_system(handle_pause.after("update_action_states"));
     }
 }
 
@@ -45,42 +92,114 @@ fn setup(
     mut state: ResMut<State<GameState>>,
     mut commands: Commands,
 ) {
-    // Set the player
-    let player = commands
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(CameraTargets::default())
+        .insert(CameraZoom::default())
+        .insert(CameraFallback::NextTarget)
+        .insert(CameraSmoothing {
+            half_life: Vec2::new(0.14, 0.2),
+        })
+        .insert(CameraOffset(Vec2::new(0.0, 24.0)))
+        .insert(CameraRoom::default())
+        .insert(CameraDeadzone::new(Vec2::new(16.0, 24.0)))
+        .insert(CameraLookAhead::new(32.0, 0.35))
+        .insert(CameraPlatformSnap::new(48.0))
+        .insert(CameraShaker::default());
+
+    commands.spawn().insert(CameraBounds {
+        min: Vec2::new(-320f32, -288f32),
+        max: Vec2::new(192f32, 160f32),
+    });
+
+    // Set the background layers, the 2d camera only draws down to a depth of -0.1
+    commands
         .spawn_bundle(SpriteBundle {
             sprite: Sprite {
-                color: Color::WHITE,
-                custom_size: Some(Vec2::new(24.0, 24.0)),
+                color: Color::MIDNIGHT_BLUE,
+                custom_size: Some(Vec2::new(640f32, 360f32)),
                 ..Default::default()
             },
-            texture: asset_server.load("sprites/bobert.png"),
+            transform: Transform::from_xyz(0f32, 0f32, -0.09f32),
             ..Default::default()
         })
-        .insert_bundle(PlayerBundle {
+        .insert(ParallaxLayer::new(Vec2::new(0.1, 0.05), Vec2::new(0f32, -96f32)).tiled(640f32));
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::DARK_GREEN,
+                custom_size: Some(Vec2::new(384f32, 96f32)),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0f32, 0f32, -0.05f32),
+            ..Default::default()
+        })
+        .insert(ParallaxLayer::new(Vec2::new(0.5, 0.3), Vec2::new(0f32, -208f32)).tiled(384f32));
+
+    commands
+        .spawn()
+        .insert(Transform::from_xyz(140f32, -200f32, 0f32))
+        .insert(CameraPathTrigger {
+            path: asset_server.load("camera/sandbox.camera.ron"),
+            half_extents: Vec2::new(16f32, 32f32),
+            blend: 0.5,
+            cutscene: true,
+            triggered: false,
+        });
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::GREEN,
+                custom_size: Some(Vec2::new(512f32, 64f32)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert_bundle(PhysicsBodyBundle {
+            body: PhysicsBody::Static,
             velocity: Vec2::new(0f32, 0f32).into(),
-            friction: Vec2::new(16.8f32, 0f32).into(),
+            friction: Vec2::new(0f32, 0f32).into(),
             collider: Collider {
-                half_extents: Vec2::new(12f32, 12f32),
+                half_extents: Vec2::new(256f32, 32f32),
             },
-            gravity_scale: GravityScale(2f32),
             ..Default::default()
         })
         .insert(Transform {
-            translation: Vec3::ZERO,
+            translation: Vec3::new(-64f32, -256.0f32, 0.0f32),
             ..Default::default()
         })
-        .id();
+        .insert(DepthLane(0f32));
 
     commands
-        .spawn_bundle(OrthographicCameraBundle::new_2d())
-        .insert(CameraTarget(player))
-        .insert(CameraSpeed(5.0))
-        .insert(CameraOffset(Vec2::new(0.0, 24.0)));
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::GREEN,
+                custom_size: Some(Vec2::new(64f32, 64f32)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert_bundle(PhysicsBodyBundle {
+            body: PhysicsBody::Static,
+            velocity: Vec2::new(0f32, 0f32).into(),
+            friction: Vec2::new(0f32, 0f32).into(),
+            collider: Collider {
+                half_extents: Vec2::new(32f32, 32f32),
+            },
+            ..Default::default()
+        })
+        .insert(Transform {
+            translation: Vec3::new(32f32, -256.0f32 + 32f32, 0.0f32),
+            ..Default::default()
+        })
+        .insert(DepthLane(0f32));
 
+    // The ground of the lane behind the playfield, drawn behind the front ground
     commands
         .spawn_bundle(SpriteBundle {
             sprite: Sprite {
-                color: Color::GREEN,
+                color: Color::DARK_GREEN,
                 custom_size: Some(Vec2::new(512f32, 64f32)),
                 ..Default::default()
             },
@@ -96,14 +215,16 @@ fn setup(
             ..Default::default()
         })
         .insert(Transform {
-            translation: Vec3::new(-64f32, -256.0f32, 0.0f32),
+            translation: Vec3::new(-64f32, -256.0f32, -0.01f32),
             ..Default::default()
-        });
+        })
+        .insert(DepthLane(48f32));
 
+    // A block in the lane behind the playfield, only solid for the bodies in that lane
     commands
         .spawn_bundle(SpriteBundle {
             sprite: Sprite {
-                color: Color::GREEN,
+                color: Color::DARK_GREEN,
                 custom_size: Some(Vec2::new(64f32, 64f32)),
                 ..Default::default()
             },
@@ -119,12 +240,69 @@ fn setup(
             ..Default::default()
         })
         .insert(Transform {
-            translation: Vec3::new(32f32, -256.0f32 + 32f32, 0.0f32),
+            translation: Vec3::new(-160f32, -256.0f32 + 32f32, 0.0f32),
             ..Default::default()
-        });
+        })
+        .insert(DepthLane(48f32));
 
-    // Continue to gameplay
-    if *state.current() != GameState::Gameplay {
-        state.set(GameState::Gameplay).unwrap();
+    // Set the pickups that unlock the abilities
+    for (ability, color, position) in [
+        (Ability::Dash, Color::ORANGE, Vec2::new(-96f32, -208f32)),
+        (
+            Ability::DoubleJump,
+            Color::CYAN,
+            Vec2::new(-256f32, -208f32),
+        ),
+        (
+            Ability::GroundPound,
+            Color::PURPLE,
+            Vec2::new(32f32, -144f32),
+        ),
+    ] {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(12f32, 12f32)),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(position.extend(0f32)),
+                ..Default::default()
+            })
+            .insert(Collider {
+                half_extents: Vec2::new(6f32, 6f32),
+            })
+            .insert(AbilityPickup(ability));
+    }
+
+    // Continue to the join screen, the players are spawned when gameplay starts
+    if *state.current() != GameState::Join {
+        state.set(GameState::Join).unwrap();
+    }
+}
+
+/// Pause and unpause the game when a player presses pause
+fn handle_pause(mut state: ResMut<State<GameState>>, players: Query<&ActionState>) {
+    if !players
+        .iter()
+        .any(|actions| actions.just_pressed(Action::Pause))
+    {
+        return;
+    }
+
+    // A transition may already be queued this frame
+    match state.current() {
+        GameState::Gameplay => {
+            let _ = state.push(GameState::Menu);
+        }
+        GameState::Menu => {
+            let _ = state.pop();
+        }
+        _ => {}
     }
 }
+
+/// Step the gameplay with the frame time
+fn update_game_time(time: Res<Time>, mut game_time: ResMut<GameTime>) {
+    game_time.set_delta_seconds(time.delta_seconds());
+}
@@ -0,0 +1,239 @@
+use bevy::prelude::*;
+
+use crate::prelude::{CameraShake, GameState, GameTime, HitEvent, PlayerAbilities, Velocity};
+
+/// The time in seconds between the flickers of an invulnerable sprite
+const FLICKER_INTERVAL: f32 = 0.08;
+
+/// The health of an entity
+#[derive(Debug, Clone, Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    /// Create a new health at its maximum
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    /// Check if the health has run out
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+/// The invulnerability frames an entity gets after taking damage
+#[derive(Debug, Default, Clone, Component)]
+pub struct Invulnerability {
+    // The time in seconds the entity is invulnerable for after taking damage
+    pub duration: f32,
+    // The remaining time the entity is invulnerable
+    pub remaining: f32,
+}
+
+impl Invulnerability {
+    /// Create new invulnerability frames with the given duration
+    pub fn new(duration: f32) -> Self {
+        Self {
+            duration,
+            remaining: 0.0,
+        }
+    }
+
+    /// Check if the entity is currently invulnerable
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0.0
+    }
+}
+
+/// Brings an entity back at a point when it dies, instead of despawning it
+#[derive(Debug, Default, Clone, Component)]
+pub struct Respawn {
+    // The position the entity comes back at
+    pub point: Vec2,
+}
+
+/// The type of damage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DamageType {
+    Melee,
+    Projectile,
+    Hazard,
+}
+
+/// Sent to deal damage to an entity
+#[derive(Debug, Clone)]
+pub struct DamageEvent {
+    // The entity that dealt the damage, if any
+    pub source: Option<Entity>,
+    // The entity that takes the damage
+    pub target: Entity,
+    // The amount of damage
+    pub amount: f32,
+    // The type of damage
+    pub damage_type: DamageType,
+    // The knockback velocity applied to the target
+    pub knockback: Vec2,
+}
+
+/// Sent when the health of an entity runs out
+#[derive(Debug, Clone)]
+pub struct DeathEvent {
+    // The entity that died
+    pub entity: Entity,
+    // The entity that dealt the final blow, if any
+    pub source: Option<Entity>,
+}
+
+/// The plugin for health and damage
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>();
+        app.add_event::<DeathEvent>();
+
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(convert_hits.label("convert_hits").after("detect_hits"))
+                .with_system(apply_damage.label("apply_damage").after("convert_hits"))
+                .with_system(handle_deaths.label("handle_deaths").after("apply_damage"))
+                .with_system(
+                    update_invulnerability
+                        .label("update_invulnerability")
+                        .after("handle_deaths"),
+                ),
+        );
+    }
+}
+
+/// Turn the hits from the hitboxes into damage
+fn convert_hits(
+    mut hit_events: EventReader<HitEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for hit in hit_events.iter() {
+        damage_events.send(DamageEvent {
+            source: Some(hit.attacker),
+            target: hit.target,
+            amount: hit.damage,
+            damage_type: DamageType::Melee,
+            knockback: hit.knockback,
+        });
+    }
+}
+
+/// Apply the damage to the health of the targets
+fn apply_damage(
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut shake_events: EventWriter<CameraShake>,
+    mut targets: Query<(
+        &mut Health,
+        Option<&mut Invulnerability>,
+        Option<&mut Velocity>,
+        Option<&PlayerAbilities>,
+    )>,
+) {
+    for damage in damage_events.iter() {
+        let (mut health, invulnerability, velocity, abilities) =
+            match targets.get_mut(damage.target) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+
+        // Ignore damage to dead or invulnerable entities
+        if health.is_dead() {
+            continue;
+        }
+        if let Some(invulnerability) = &invulnerability {
+            if invulnerability.is_active() {
+                continue;
+            }
+        }
+        if abilities.map_or(false, |abilities| abilities.is_invulnerable()) {
+            continue;
+        }
+
+        health.current = (health.current - damage.amount).max(0.0);
+        shake_events.send(CameraShake {
+            trauma: 0.3,
+            direction: damage.knockback,
+        });
+        if let Some(mut velocity) = velocity {
+            velocity.0 = damage.knockback;
+        }
+        if let Some(mut invulnerability) = invulnerability {
+            invulnerability.remaining = invulnerability.duration;
+        }
+
+        if health.is_dead() {
+            death_events.send(DeathEvent {
+                entity: damage.target,
+                source: damage.source,
+            });
+        }
+    }
+}
+
+/// Respawn the dead entities that can respawn and despawn the others
+fn handle_deaths(
+    mut commands: Commands,
+    mut death_events: EventReader<DeathEvent>,
+    mut entities: Query<(
+        &mut Health,
+        Option<&Respawn>,
+        Option<&mut Transform>,
+        Option<&mut Velocity>,
+        Option<&mut Invulnerability>,
+    )>,
+) {
+    for death in death_events.iter() {
+        let (mut health, respawn, transform, velocity, invulnerability) =
+            match entities.get_mut(death.entity) {
+                Ok(entity) => entity,
+                Err(_) => continue,
+            };
+
+        let respawn = match respawn {
+            Some(respawn) => respawn,
+            None => {
+                commands.entity(death.entity).despawn_recursive();
+                continue;
+            }
+        };
+
+        health.current = health.max;
+        if let Some(mut transform) = transform {
+            transform.translation.x = respawn.point.x;
+            transform.translation.y = respawn.point.y;
+        }
+        if let Some(mut velocity) = velocity {
+            velocity.0 = Vec2::ZERO;
+        }
+        if let Some(mut invulnerability) = invulnerability {
+            invulnerability.remaining = invulnerability.duration;
+        }
+    }
+}
+
+/// Tick the invulnerability frames and flicker the sprites of invulnerable entities
+/// The sprite is only touched while invulnerable, and made visible again once when it ends
+fn update_invulnerability(
+    time: Res<GameTime>,
+    mut entities: Query<(&mut Invulnerability, Option<&mut Visibility>)>,
+) {
+    for (mut invulnerability, visibility) in entities.iter_mut() {
+        if !invulnerability.is_active() {
+            continue;
+        }
+        invulnerability.remaining = (invulnerability.remaining - time.delta_seconds()).max(0.0);
+
+        if let Some(mut visibility) = visibility {
+            visibility.is_visible = !invulnerability.is_active()
+                || (invulnerability.remaining / FLICKER_INTERVAL) as i32 % 2 == 0;
+        }
+    }
+}
@@ -0,0 +1,764 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::{GameState, PlayerControllerState, PlayerInputState};
+
+/// The name of the input config file in the config directory of the game
+const INPUT_CONFIG_FILE: &str = "input.ron";
+
+/// The value an analog binding has to reach to count as pressed
+const PRESS_THRESHOLD: f32 = 0.5;
+
+/// The actions a player can perform
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Jump,
+    Run,
+    Attack,
+    Dash,
+    GroundPound,
+    Interact,
+    Pause,
+    Menu,
+    Flip,
+    LaneFront,
+    LaneBack,
+}
+
+impl Action {
+    /// All the actions
+    pub const ALL: [Action; 15] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Jump,
+        Action::Run,
+        Action::Attack,
+        Action::Dash,
+        Action::GroundPound,
+        Action::Interact,
+        Action::Pause,
+        Action::Menu,
+        Action::Flip,
+        Action::LaneFront,
+        Action::LaneBack,
+    ];
+}
+
+/// The direction of a gamepad axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+impl AxisDirection {
+    /// The sign of the direction
+    pub fn sign(&self) -> f32 {
+        match self {
+            AxisDirection::Positive => 1.0,
+            AxisDirection::Negative => -1.0,
+        }
+    }
+}
+
+/// An input that can be bound to an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButtonType),
+    GamepadAxis(GamepadAxisType, AxisDirection),
+}
+
+/// The response curve applied to the analog sticks after the deadzones
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ResponseCurve {
+    Linear,
+    Quadratic,
+    Cubic,
+    Power(f32),
+}
+
+impl ResponseCurve {
+    /// Apply the curve to a value between 0 and 1
+    pub fn apply(&self, value: f32) -> f32 {
+        match self {
+            ResponseCurve::Linear => value,
+            ResponseCurve::Quadratic => value * value,
+            ResponseCurve::Cubic => value * value * value,
+            ResponseCurve::Power(exponent) => value.powf(*exponent),
+        }
+    }
+}
+
+/// The deadzones and response curve of the analog sticks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StickSettings {
+    // The distance from the center below which the stick reads as zero
+    pub inner_deadzone: f32,
+    // The distance from the center above which the stick reads as fully pushed
+    pub outer_deadzone: f32,
+    // The curve applied to the distance between the deadzones
+    pub curve: ResponseCurve,
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        Self {
+            inner_deadzone: 0.2,
+            outer_deadzone: 0.95,
+            curve: ResponseCurve::Linear,
+        }
+    }
+}
+
+impl StickSettings {
+    /// Apply the deadzones and the response curve to a stick position
+    /// The deadzones are radial, so the direction of the stick is kept
+    pub fn apply(&self, stick: Vec2) -> Vec2 {
+        let magnitude = stick.length();
+        if magnitude <= self.inner_deadzone {
+            return Vec2::ZERO;
+        }
+
+        let range = (self.outer_deadzone - self.inner_deadzone).max(f32::EPSILON);
+        let scaled = ((magnitude - self.inner_deadzone) / range).clamp(0.0, 1.0);
+        stick / magnitude * self.curve.apply(scaled)
+    }
+}
+
+/// Maps the actions to their bindings
+/// Loaded from and saved to the config directory of the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+    #[serde(default)]
+    pub stick: StickSettings,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        let mut bindings = HashMap::new();
+        bindings.insert(
+            Action::MoveLeft,
+            vec![
+                Binding::Key(KeyCode::A),
+                Binding::Key(KeyCode::Left),
+                Binding::GamepadAxis(GamepadAxisType::LeftStickX, AxisDirection::Negative),
+                Binding::GamepadButton(GamepadButtonType::DPadLeft),
+            ],
+        );
+        bindings.insert(
+            Action::MoveRight,
+            vec![
+                Binding::Key(KeyCode::D),
+                Binding::Key(KeyCode::Right),
+                Binding::GamepadAxis(GamepadAxisType::LeftStickX, AxisDirection::Positive),
+                Binding::GamepadButton(GamepadButtonType::DPadRight),
+            ],
+        );
+        bindings.insert(
+            Action::MoveUp,
+            vec![
+                Binding::Key(KeyCode::W),
+                Binding::Key(KeyCode::Up),
+                Binding::GamepadAxis(GamepadAxisType::LeftStickY, AxisDirection::Positive),
+                Binding::GamepadButton(GamepadButtonType::DPadUp),
+            ],
+        );
+        bindings.insert(
+            Action::MoveDown,
+            vec![
+                Binding::Key(KeyCode::S),
+                Binding::Key(KeyCode::Down),
+                Binding::GamepadAxis(GamepadAxisType::LeftStickY, AxisDirection::Negative),
+                Binding::GamepadButton(GamepadButtonType::DPadDown),
+            ],
+        );
+        bindings.insert(
+            Action::Jump,
+            vec![
+                Binding::Key(KeyCode::Space),
+                Binding::GamepadButton(GamepadButtonType::South),
+            ],
+        );
+        bindings.insert(
+            Action::Run,
+            vec![
+                Binding::Key(KeyCode::LShift),
+                Binding::GamepadButton(GamepadButtonType::West),
+            ],
+        );
+        bindings.insert(
+            Action::Attack,
+            vec![
+                Binding::Key(KeyCode::J),
+                Binding::Mouse(MouseButton::Left),
+                Binding::GamepadButton(GamepadButtonType::East),
+            ],
+        );
+        bindings.insert(
+            Action::Dash,
+            vec![
+                Binding::Key(KeyCode::K),
+                Binding::Mouse(MouseButton::Right),
+                Binding::GamepadButton(GamepadButtonType::RightTrigger),
+            ],
+        );
+        bindings.insert(
+            Action::GroundPound,
+            vec![
+                Binding::Key(KeyCode::S),
+                Binding::GamepadButton(GamepadButtonType::DPadDown),
+            ],
+        );
+        bindings.insert(
+            Action::Interact,
+            vec![
+                Binding::Key(KeyCode::E),
+                Binding::GamepadButton(GamepadButtonType::North),
+            ],
+        );
+        bindings.insert(
+            Action::Pause,
+            vec![
+                Binding::Key(KeyCode::Escape),
+                Binding::GamepadButton(GamepadButtonType::Start),
+            ],
+        );
+        bindings.insert(
+            Action::Menu,
+            vec![
+                Binding::Key(KeyCode::Tab),
+                Binding::GamepadButton(GamepadButtonType::Select),
+            ],
+        );
+        bindings.insert(
+            Action::Flip,
+            vec![
+                Binding::Key(KeyCode::F),
+                Binding::GamepadButton(GamepadButtonType::LeftTrigger),
+            ],
+        );
+        bindings.insert(
+            Action::LaneFront,
+            vec![
+                Binding::Key(KeyCode::Q),
+                Binding::GamepadButton(GamepadButtonType::LeftTrigger2),
+            ],
+        );
+        bindings.insert(
+            Action::LaneBack,
+            vec![
+                Binding::Key(KeyCode::R),
+                Binding::GamepadButton(GamepadButtonType::RightTrigger2),
+            ],
+        );
+
+        Self {
+            bindings,
+            stick: Default::default(),
+        }
+    }
+}
+
+impl ActionMap {
+    /// The path to the input config file
+    pub fn config_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("highground").join(INPUT_CONFIG_FILE))
+    }
+
+    /// Load the action map from the config file, or use the defaults if there is none
+    pub fn load() -> Self {
+        let path = match Self::config_path() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return Self::default(),
+        };
+
+        match ron::from_str::<ActionMap>(&contents) {
+            Ok(mut map) => {
+                // Fill in the actions that are missing from older configs
+                for (action, bindings) in Self::default().bindings {
+                    map.bindings.entry(action).or_insert(bindings);
+                }
+                map
+            }
+            Err(err) => {
+                warn!("Failed to parse the input config {:?}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save the action map to the config file
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::config_path()
+            .ok_or_else(|| anyhow::anyhow!("Could not find the config directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(
+            path,
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())?,
+        )?;
+        Ok(())
+    }
+
+    /// The bindings of an action
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Add a binding to an action
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Remove a binding from an action
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+
+    /// Replace a binding of an action, keeping its position
+    /// Adds the binding when the old binding was removed in the meantime
+    pub fn rebind(&mut self, action: Action, old: Binding, new: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        // Remove the new binding first, so the position of the old binding is looked up in the final list
+        bindings.retain(|bound| *bound == old || *bound != new);
+        match bindings.iter().position(|bound| *bound == old) {
+            Some(index) => bindings[index] = new,
+            None => bindings.push(new),
+        }
+    }
+
+    /// Remove all bindings from an action
+    pub fn clear(&mut self, action: Action) {
+        self.bindings.insert(action, Vec::new());
+    }
+}
+
+/// A request to bind the next pressed input to an action
+#[derive(Debug, Clone)]
+pub struct RebindRequest {
+    // The action to bind the input to
+    pub action: Action,
+    // The binding to replace, or none to add a new binding
+    pub replace: Option<Binding>,
+}
+
+/// The rebind that is waiting for an input, if any
+#[derive(Debug, Default)]
+pub struct PendingRebind(pub Option<RebindRequest>);
+
+/// The binding that finished the last rebind
+/// Ignored until it is released, so the press doesn't also fire the action
+#[derive(Debug, Default)]
+struct CapturedBinding(Option<Binding>);
+
+/// The state of the actions for a player
+#[derive(Debug, Default, Clone, Component)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    previous: HashSet<Action>,
+    values: HashMap<Action, f32>,
+    // Hold back the actions that are held on the next update
+    ignore_held: bool,
+    // The actions that are held back until they are released
+    held: HashSet<Action>,
+}
+
+impl ActionState {
+    /// Create an action state that ignores the actions held on its first update until they are released
+    /// Used for new players, so the press that started the game doesn't also fire an action
+    pub fn ignoring_held() -> Self {
+        Self {
+            ignore_held: true,
+            ..Default::default()
+        }
+    }
+
+    /// Check if the action is held down
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    /// Check if the action was pressed this frame
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action) && !self.previous.contains(&action)
+    }
+
+    /// Check if the action was released this frame
+    pub fn just_released(&self, action: Action) -> bool {
+        !self.pressed.contains(&action) && self.previous.contains(&action)
+    }
+
+    /// The analog value of the action between 0 and 1
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.0)
+    }
+
+    /// The analog values of the actions that are held at all
+    pub fn values(&self) -> &HashMap<Action, f32> {
+        &self.values
+    }
+
+    /// Advance to the next frame with the given analog values
+    pub fn update(&mut self, mut values: HashMap<Action, f32>) {
+        if self.ignore_held {
+            self.ignore_held = false;
+            self.held = values
+                .iter()
+                .filter(|(_, value)| **value >= PRESS_THRESHOLD)
+                .map(|(action, _)| *action)
+                .collect();
+        }
+        self.held
+            .retain(|action| values.get(action).map_or(false, |value| *value > 0.0));
+        values.retain(|action, _| !self.held.contains(action));
+
+        self.previous = std::mem::take(&mut self.pressed);
+        self.set(values);
+    }
+
+    /// Replace the analog values of the current frame
+    pub fn set(&mut self, values: HashMap<Action, f32>) {
+        self.values = values;
+        self.values.retain(|_, value| *value > 0.0);
+        self.pressed = self
+            .values
+            .iter()
+            .filter(|(_, value)| **value >= PRESS_THRESHOLD)
+            .map(|(action, _)| *action)
+            .collect();
+    }
+}
+
+/// The gamepads that are currently connected, in the order they were connected
+#[derive(Debug, Default)]
+pub struct ConnectedGamepads(pub Vec<Gamepad>);
+
+/// The gamepad assigned to a player
+#[derive(Debug, Default, Clone, Component)]
+pub struct GamepadAssignment {
+    // The assigned gamepad, kept while disconnected so it returns to the same player
+    pub gamepad: Option<Gamepad>,
+    // The flag for if the assigned gamepad is connected
+    pub connected: bool,
+}
+
+/// Marks the player the keyboard and mouse are bound to
+#[derive(Debug, Default, Clone, Component)]
+pub struct KeyboardAssignment;
+
+/// The raw input the actions are read from
+struct RawInput<'a> {
+    keys: &'a Input<KeyCode>,
+    mouse: &'a Input<MouseButton>,
+    gamepad_buttons: &'a Input<GamepadButton>,
+    gamepad_axes: &'a Axis<GamepadAxis>,
+    stick: &'a StickSettings,
+}
+
+impl RawInput<'_> {
+    /// The value of a binding for the controller, between 0 and 1
+    fn value(&self, binding: Binding, controller: PlayerControllerState) -> f32 {
+        match (binding, controller) {
+            (Binding::Key(key), PlayerControllerState::Keyboard) => {
+                self.keys.pressed(key) as i32 as f32
+            }
+            (Binding::Mouse(button), PlayerControllerState::Keyboard) => {
+                self.mouse.pressed(button) as i32 as f32
+            }
+            (Binding::GamepadButton(button), PlayerControllerState::Gamepad(id)) => {
+                self.gamepad_buttons
+                    .pressed(GamepadButton(Gamepad(id), button)) as i32 as f32
+            }
+            (Binding::GamepadAxis(axis, direction), PlayerControllerState::Gamepad(id)) => {
+                (self.axis(Gamepad(id), axis) * direction.sign()).clamp(0.0, 1.0)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The value of a gamepad axis with the deadzones and response curve applied
+    /// Missing axis values read as zero
+    fn axis(&self, gamepad: Gamepad, axis: GamepadAxisType) -> f32 {
+        let raw = |axis| {
+            self.gamepad_axes
+                .get(GamepadAxis(gamepad, axis))
+                .unwrap_or(0.0)
+        };
+
+        // The sticks are processed as a whole so the deadzone is round
+        match axis {
+            GamepadAxisType::LeftStickX | GamepadAxisType::LeftStickY => {
+                let stick = self.stick.apply(Vec2::new(
+                    raw(GamepadAxisType::LeftStickX),
+                    raw(GamepadAxisType::LeftStickY),
+                ));
+                if axis == GamepadAxisType::LeftStickX {
+                    stick.x
+                } else {
+                    stick.y
+                }
+            }
+            GamepadAxisType::RightStickX | GamepadAxisType::RightStickY => {
+                let stick = self.stick.apply(Vec2::new(
+                    raw(GamepadAxisType::RightStickX),
+                    raw(GamepadAxisType::RightStickY),
+                ));
+                if axis == GamepadAxisType::RightStickX {
+                    stick.x
+                } else {
+                    stick.y
+                }
+            }
+            _ => self.stick.apply(Vec2::new(raw(axis), 0.0)).x,
+        }
+    }
+}
+
+/// The plugin for the action mapping
+pub struct ActionInputPlugin;
+
+impl Plugin for ActionInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ActionMap::load());
+        app.init_resource::<PendingRebind>();
+        app.init_resource::<CapturedBinding>();
+        app.init_resource::<ConnectedGamepads>();
+
+        app.add_system(track_gamepads.label("track_gamepads"));
+        app.add_system(
+            assign_gamepads
+                .label("assign_gamepads")
+                .after("track_gamepads"),
+        );
+        app.add_system(handle_rebinding.label("handle_rebinding"));
+        app.add_system(
+            update_action_states
+                .label("update_action_states")
+                .after("handle_rebinding")
+                .after("update_controller_state"),
+        );
+    }
+}
+
+/// Track the connected gamepads, and pause the game when a player loses their gamepad
+fn track_gamepads(
+    mut events: EventReader<GamepadEvent>,
+    mut connected: ResMut<ConnectedGamepads>,
+    mut state: ResMut<State<GameState>>,
+    mut players: Query<(&mut GamepadAssignment, &PlayerInputState)>,
+) {
+    for GamepadEvent(gamepad, event) in events.iter() {
+        match event {
+            GamepadEventType::Connected => {
+                info!("Gamepad {} connected", gamepad.0);
+                if !connected.0.contains(gamepad) {
+                    connected.0.push(*gamepad);
+                }
+
+                // Give the gamepad back to the player it was assigned to
+                for (mut assignment, _) in players.iter_mut() {
+                    if assignment.gamepad == Some(*gamepad) {
+                        assignment.connected = true;
+                    }
+                }
+            }
+            GamepadEventType::Disconnected => {
+                info!("Gamepad {} disconnected", gamepad.0);
+                connected.0.retain(|connected| connected != gamepad);
+
+                let mut lost_active_gamepad = false;
+                for (mut assignment, input) in players.iter_mut() {
+                    if assignment.gamepad == Some(*gamepad) {
+                        assignment.connected = false;
+                        lost_active_gamepad |=
+                            input.controller == PlayerControllerState::Gamepad(gamepad.0);
+                    }
+                }
+
+                // Pause when a player loses the gamepad they are playing with
+                if lost_active_gamepad && *state.current() == GameState::Gameplay {
+                    // A transition may already be queued this frame
+                    let _ = state.push(GameState::Menu);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Assign the connected gamepads that are not in use to the players without a gamepad
+/// The player on the keyboard is left without one, so a free gamepad doesn't control it
+fn assign_gamepads(
+    connected: Res<ConnectedGamepads>,
+    mut players: Query<&mut GamepadAssignment, Without<KeyboardAssignment>>,
+) {
+    let assigned = players
+        .iter()
+        .filter_map(|assignment| assignment.gamepad)
+        .collect::<Vec<_>>();
+    let mut free = connected
+        .0
+        .iter()
+        .filter(|gamepad| !assigned.contains(gamepad));
+
+    for mut assignment in players.iter_mut() {
+        if assignment.gamepad.is_some() {
+            continue;
+        }
+        match free.next() {
+            Some(gamepad) => {
+                assignment.gamepad = Some(*gamepad);
+                assignment.connected = true;
+            }
+            None => break,
+        }
+    }
+}
+
+/// Update the action states of the players from their controllers
+fn update_action_states(
+    map: Res<ActionMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<ConnectedGamepads>,
+    mut captured: ResMut<CapturedBinding>,
+    mut players: Query<(&mut ActionState, &PlayerInputState)>,
+) {
+    let raw = RawInput {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad_axes: &gamepad_axes,
+        stick: &map.stick,
+    };
+
+    // Stop ignoring the binding that finished a rebind once it is released on every controller
+    if let Some(binding) = captured.0 {
+        let mut controllers = std::iter::once(PlayerControllerState::Keyboard).chain(
+            gamepads
+                .0
+                .iter()
+                .map(|gamepad| PlayerControllerState::Gamepad(gamepad.0)),
+        );
+        if !controllers.any(|controller| raw.value(binding, controller) > 0.0) {
+            captured.0 = None;
+        }
+    }
+
+    for (mut actions, input) in players.iter_mut() {
+        let values = map
+            .bindings
+            .iter()
+            .map(|(action, bindings)| {
+                // Use the strongest of the bindings
+                let value = bindings
+                    .iter()
+                    .filter(|binding| captured.0 != Some(**binding))
+                    .map(|binding| raw.value(*binding, input.controller))
+                    .fold(0.0, f32::max);
+                (*action, value)
+            })
+            .collect();
+        actions.update(values);
+    }
+}
+
+/// Bind the next pressed input to the action of the pending rebind
+/// A stick or trigger is captured when it is pushed past the press threshold
+fn handle_rebinding(
+    mut pending: ResMut<PendingRebind>,
+    mut captured: ResMut<CapturedBinding>,
+    mut map: ResMut<ActionMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut gamepad_events: EventReader<GamepadEvent>,
+    mut axis_values: Local<HashMap<GamepadAxis, f32>>,
+) {
+    // Keep track of the axes even without a pending rebind,
+    // so an axis that is already held doesn't get captured
+    let mut pushed_axis = None;
+    for GamepadEvent(gamepad, event) in gamepad_events.iter() {
+        if let GamepadEventType::AxisChanged(axis_type, value) = event {
+            let axis = GamepadAxis(*gamepad, *axis_type);
+            let previous = axis_values.insert(axis, *value).unwrap_or(0.0);
+            if value.abs() >= PRESS_THRESHOLD && previous.abs() < PRESS_THRESHOLD {
+                let direction = if *value > 0.0 {
+                    AxisDirection::Positive
+                } else {
+                    AxisDirection::Negative
+                };
+                pushed_axis = pushed_axis.or(Some(Binding::GamepadAxis(*axis_type, direction)));
+            }
+        }
+    }
+
+    let request = match &pending.0 {
+        Some(request) => request.clone(),
+        None => return,
+    };
+
+    let binding = keys
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::GamepadButton(button.1))
+        })
+        .or(pushed_axis);
+    let binding = match binding {
+        Some(binding) => binding,
+        None => return,
+    };
+
+    match request.replace {
+        Some(old) => map.rebind(request.action, old, binding),
+        None => map.bind(request.action, binding),
+    }
+    pending.0 = None;
+    captured.0 = Some(binding);
+
+    if let Err(err) = map.save() {
+        warn!("Failed to save the input config: {}", err);
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::prelude::{Action, ActionState, GameState, GameTime};
+
+/// The time in seconds the presses are kept in the input buffers
+const BUFFER_DURATION: f32 = 1.0;
+
+/// The name of the combo for a stronger attack
+pub const POWER_STRIKE: &str = "power_strike";
+
+/// A press of an action in the input buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BufferedInput {
+    // The action that was pressed
+    pub action: Action,
+    // The time of the press on the clock of the buffer
+    pub time: f32,
+}
+
+/// The time-stamped history of the presses of a player
+/// The clock only runs during gameplay, so pausing doesn't break the timing
+#[derive(Debug, Default, Clone, Component)]
+pub struct InputBuffer {
+    history: VecDeque<BufferedInput>,
+    time: f32,
+}
+
+impl InputBuffer {
+    /// The current time on the clock of the buffer
+    pub fn now(&self) -> f32 {
+        self.time
+    }
+
+    /// The presses in the buffer, from old to new
+    pub fn history(&self) -> impl Iterator<Item = &BufferedInput> {
+        self.history.iter()
+    }
+
+    /// Forget all the presses, so a matched sequence doesn't match again
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Check if the latest presses are the sequence, in order, within the window in seconds
+    /// The last action of the sequence has to be pressed this frame
+    /// Presses of actions that are not in the sequence are skipped, since one input can press several actions
+    pub fn matches(&self, sequence: &[Action], window: f32) -> bool {
+        let presses = self
+            .history
+            .iter()
+            .filter(|input| sequence.contains(&input.action))
+            .collect::<Vec<_>>();
+        if sequence.is_empty() || sequence.len() > presses.len() {
+            return false;
+        }
+
+        let latest = presses[presses.len() - 1];
+        let first = presses[presses.len() - sequence.len()];
+        latest.time == self.time
+            && latest.time - first.time <= window
+            && presses[presses.len() - sequence.len()..]
+                .iter()
+                .map(|input| input.action)
+                .eq(sequence.iter().copied())
+    }
+
+    /// The first press of the action after the time
+    pub fn press_after(&self, action: Action, time: f32) -> Option<&BufferedInput> {
+        self.history
+            .iter()
+            .find(|input| input.action == action && input.time > time)
+    }
+
+    /// Advance the clock and add the presses of this frame
+    fn update(&mut self, delta: f32, actions: &ActionState) {
+        self.time += delta;
+        for action in Action::ALL {
+            if actions.just_pressed(action) {
+                self.history.push_back(BufferedInput {
+                    action,
+                    time: self.time,
+                });
+            }
+        }
+
+        while let Some(input) = self.history.front() {
+            if self.time - input.time <= BUFFER_DURATION {
+                break;
+            }
+            self.history.pop_front();
+        }
+    }
+}
+
+/// The grade of a timed press
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Grade {
+    Perfect,
+    Good,
+    Miss,
+}
+
+/// The distance in seconds from the target time a press is graded with
+#[derive(Debug, Clone, Copy)]
+pub struct TimingWindow {
+    // The distance for a perfect press
+    pub perfect: f32,
+    // The distance for a good press
+    pub good: f32,
+}
+
+impl Default for TimingWindow {
+    fn default() -> Self {
+        Self {
+            perfect: 0.05,
+            good: 0.1,
+        }
+    }
+}
+
+impl TimingWindow {
+    /// Grade a press by its distance from the target time
+    pub fn grade(&self, press: f32, target: f32) -> Grade {
+        let distance = (press - target).abs();
+        if distance <= self.perfect {
+            Grade::Perfect
+        } else if distance <= self.good {
+            Grade::Good
+        } else {
+            Grade::Miss
+        }
+    }
+}
+
+/// A timed press the player has to make, like pressing attack on impact
+/// Presses before the window count as a miss, so mashing doesn't work
+#[derive(Debug, Clone, Component)]
+pub struct ActionCommand {
+    // The action to press
+    pub action: Action,
+    // The time of the impact on the clock of the input buffer
+    pub target: f32,
+    // The windows around the impact
+    pub window: TimingWindow,
+    // The time the command started on the clock of the input buffer
+    pub started: f32,
+}
+
+impl ActionCommand {
+    /// Create a command to press the action a delay in seconds from now
+    /// Only the presses from the next frame on count
+    pub fn new(buffer: &InputBuffer, action: Action, delay: f32, window: TimingWindow) -> Self {
+        Self {
+            action,
+            target: buffer.now() + delay,
+            window,
+            started: buffer.now(),
+        }
+    }
+
+    /// Grade the command against the presses in the buffer
+    /// Returns none while the player can still press in time
+    pub fn grade(&self, buffer: &InputBuffer) -> Option<Grade> {
+        match buffer.press_after(self.action, self.started) {
+            // Pressing before the window is too early
+            Some(press) if press.time < self.target - self.window.good => Some(Grade::Miss),
+            Some(press) => Some(self.window.grade(press.time, self.target)),
+            None if buffer.now() > self.target + self.window.good => Some(Grade::Miss),
+            None => None,
+        }
+    }
+}
+
+/// Sent when an action command of a player is graded, the command is removed after
+#[derive(Debug, Clone)]
+pub struct ActionCommandEvent {
+    pub player: Entity,
+    pub action: Action,
+    pub grade: Grade,
+}
+
+/// A named sequence of presses
+#[derive(Debug, Clone)]
+pub struct Combo {
+    // The name the combo is reported with
+    pub name: &'static str,
+    // The actions to press in order
+    pub sequence: Vec<Action>,
+    // The time in seconds the whole sequence has to be pressed in
+    pub window: f32,
+}
+
+/// The combos that are checked against the input buffers of the players
+#[derive(Debug, Clone)]
+pub struct Combos(pub Vec<Combo>);
+
+impl Default for Combos {
+    fn default() -> Self {
+        Self(vec![Combo {
+            name: POWER_STRIKE,
+            sequence: vec![Action::MoveDown, Action::MoveDown, Action::Attack],
+            window: 0.5,
+        }])
+    }
+}
+
+/// Sent when a player presses a combo
+#[derive(Debug, Clone)]
+pub struct ComboEvent {
+    pub player: Entity,
+    pub combo: &'static str,
+}
+
+/// The plugin for the input buffers, combos and action commands
+pub struct InputBufferPlugin;
+
+impl Plugin for InputBufferPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Combos>();
+        app.add_event::<ComboEvent>();
+        app.add_event::<ActionCommandEvent>();
+
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(
+                    update_input_buffers
+                        .label("update_input_buffers")
+                        .after("update_action_states")
+                        .after("replay_inputs"),
+                )
+                .with_system(
+                    grade_action_commands
+                        .label("grade_action_commands")
+                        .after("update_input_buffers"),
+                )
+                // Combos clear the buffer, so the presses are graded first
+                .with_system(
+                    detect_combos
+                        .label("detect_combos")
+                        .after("grade_action_commands"),
+                ),
+        );
+    }
+}
+
+/// Add the presses of the frame to the input buffers
+fn update_input_buffers(time: Res<GameTime>, mut players: Query<(&mut InputBuffer, &ActionState)>) {
+    for (mut buffer, actions) in players.iter_mut() {
+        buffer.update(time.delta_seconds(), actions);
+    }
+}
+
+/// Report the combos the players pressed, the longest matching combo wins
+fn detect_combos(
+    combos: Res<Combos>,
+    mut combo_events: EventWriter<ComboEvent>,
+    mut players: Query<(Entity, &mut InputBuffer)>,
+) {
+    for (player, mut buffer) in players.iter_mut() {
+        let combo = combos
+            .0
+            .iter()
+            .filter(|combo| buffer.matches(&combo.sequence, combo.window))
+            .max_by_key(|combo| combo.sequence.len());
+
+        if let Some(combo) = combo {
+            combo_events.send(ComboEvent {
+                player,
+                combo: combo.name,
+            });
+            buffer.clear();
+        }
+    }
+}
+
+/// Grade the action commands once they are pressed or their window has passed
+fn grade_action_commands(
+    mut commands: Commands,
+    mut command_events: EventWriter<ActionCommandEvent>,
+    players: Query<(Entity, &InputBuffer, &ActionCommand)>,
+) {
+    for (player, buffer, command) in players.iter() {
+        let grade = match command.grade(buffer) {
+            Some(grade) => grade,
+            None => continue,
+        };
+
+        command_events.send(ActionCommandEvent {
+            player,
+            action: command.action,
+            grade,
+        });
+        commands.entity(player).remove::<ActionCommand>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// The time in seconds of a frame at 60 frames per second
+    const DELTA: f32 = 1.0 / 60.0;
+
+    /// Step the buffer a frame with the actions held down
+    fn step(buffer: &mut InputBuffer, actions: &mut ActionState, held: &[Action]) {
+        actions.update(
+            held.iter()
+                .map(|action| (*action, 1.0))
+                .collect::<HashMap<_, _>>(),
+        );
+        buffer.update(DELTA, actions);
+    }
+
+    #[test]
+    fn matches_combo_with_actions_on_the_same_input() {
+        let mut buffer = InputBuffer::default();
+        let mut actions = ActionState::default();
+        let combo = &Combos::default().0[0];
+
+        // Down presses both move down and ground pound
+        for held in [
+            &[Action::MoveDown, Action::GroundPound][..],
+            &[],
+            &[Action::MoveDown, Action::GroundPound],
+            &[],
+        ] {
+            step(&mut buffer, &mut actions, held);
+            assert!(!buffer.matches(&combo.sequence, combo.window));
+        }
+        step(&mut buffer, &mut actions, &[Action::Attack]);
+        assert!(buffer.matches(&combo.sequence, combo.window));
+    }
+
+    #[test]
+    fn grades_press_from_the_next_frame() {
+        let mut buffer = InputBuffer::default();
+        let mut actions = ActionState::default();
+
+        // The press on the frame the command starts doesn't count
+        step(&mut buffer, &mut actions, &[Action::Jump]);
+        let command =
+            ActionCommand::new(&buffer, Action::Jump, 2.0 * DELTA, TimingWindow::default());
+        assert_eq!(command.grade(&buffer), None);
+
+        step(&mut buffer, &mut actions, &[]);
+        assert_eq!(command.grade(&buffer), None);
+        step(&mut buffer, &mut actions, &[Action::Jump]);
+        assert_eq!(command.grade(&buffer), Some(Grade::Perfect));
+    }
+
+    #[test]
+    fn grades_missing_press_as_miss() {
+        let mut buffer = InputBuffer::default();
+        let mut actions = ActionState::default();
+        let command = ActionCommand::new(&buffer, Action::Jump, 0.0, TimingWindow::default());
+
+        while buffer.now() <= command.target + command.window.good {
+            assert_eq!(command.grade(&buffer), None);
+            step(&mut buffer, &mut actions, &[]);
+        }
+        assert_eq!(command.grade(&buffer), Some(Grade::Miss));
+    }
+}
@@ -1,14 +1,36 @@
 //! The lib for highground
 pub mod camera;
+pub mod camera_path;
+pub mod combat;
+pub mod coop;
+pub mod dimension;
 pub mod game;
+pub mod health;
+pub mod input;
+pub mod input_buffer;
+pub mod parallax;
 pub mod physics;
 pub mod player;
+pub mod replay;
+pub mod state_machine;
+pub mod tuning;
 
 pub mod prelude {
     pub use crate::camera::*;
+    pub use crate::camera_path::*;
+    pub use crate::combat::*;
+    pub use crate::coop::*;
+    pub use crate::dimension::*;
     pub use crate::game::*;
+    pub use crate::health::*;
+    pub use crate::input::*;
+    pub use crate::input_buffer::*;
+    pub use crate::parallax::*;
     pub use crate::physics::*;
     pub use crate::player::*;
+    pub use crate::replay::*;
+    pub use crate::state_machine::*;
+    pub use crate::tuning::*;
 }
 
 /// The entrypoint for the game
@@ -0,0 +1,108 @@
+use bevy::{prelude::*, render::camera::CAMERA_2D};
+
+/// A background layer that scrolls slower than the playfield
+#[derive(Debug, Clone, Component)]
+pub struct ParallaxLayer {
+    // How much the layer moves with the world on each axis,
+    // 1 moves with the playfield and 0 stays in place on the screen
+    pub factor: Vec2,
+    // The position of the layer when the camera is at the origin
+    pub origin: Vec2,
+    // The width of the sprite to repeat the layer with horizontally, if any
+    // Should be at least the width of the view so the copies cover the screen
+    pub tile_width: Option<f32>,
+}
+
+impl ParallaxLayer {
+    /// Create a layer with the given scroll factor
+    pub fn new(factor: Vec2, origin: Vec2) -> Self {
+        Self {
+            factor,
+            origin,
+            tile_width: None,
+        }
+    }
+
+    /// Repeat the layer infinitely in the horizontal direction
+    pub fn tiled(mut self, width: f32) -> Self {
+        self.tile_width = Some(width);
+        self
+    }
+
+    /// The position of the layer for the camera position
+    pub fn position(&self, camera: Vec2) -> Vec2 {
+        let mut position = self.origin + camera * (Vec2::ONE - self.factor);
+
+        // Move the layer by whole tiles so it stays under the camera
+        if let Some(width) = self.tile_width.filter(|width| *width > 0.0) {
+            position.x += ((camera.x - position.x) / width).round() * width;
+        }
+        position
+    }
+}
+
+/// A copy of a tiled layer next to it
+#[derive(Debug, Clone, Component)]
+struct ParallaxTile;
+
+/// The plugin for the parallax layers
+pub struct ParallaxPlugin;
+
+impl Plugin for ParallaxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_parallax_tiles);
+        app.add_system(
+            update_parallax
+                .label("update_parallax")
+                .after("update_position")
+                .after("apply_scripted_cameras")
+                .after("apply_shake"),
+        );
+    }
+}
+
+/// Add a copy on both sides of the new tiled layers
+fn spawn_parallax_tiles(
+    mut commands: Commands,
+    layers: Query<(Entity, &ParallaxLayer, &Sprite, &Handle<Image>), Added<ParallaxLayer>>,
+) {
+    for (entity, layer, sprite, texture) in layers.iter() {
+        let width = match layer.tile_width {
+            Some(width) => width,
+            None => continue,
+        };
+
+        commands.entity(entity).with_children(|parent| {
+            for side in [-1.0, 1.0] {
+                parent
+                    .spawn_bundle(SpriteBundle {
+                        sprite: sprite.clone(),
+                        texture: texture.clone(),
+                        transform: Transform::from_xyz(side * width, 0.0, 0.0),
+                        ..Default::default()
+                    })
+                    .insert(ParallaxTile);
+            }
+        });
+    }
+}
+
+/// Position the layers relative to the game camera
+fn update_parallax(
+    cameras: Query<(&Camera, &Transform), Without<ParallaxLayer>>,
+    mut layers: Query<(&ParallaxLayer, &mut Transform)>,
+) {
+    let camera = match cameras
+        .iter()
+        .find(|(camera, _)| camera.name.as_deref() == Some(CAMERA_2D))
+    {
+        Some((_, transform)) => transform.translation.truncate(),
+        None => return,
+    };
+
+    for (layer, mut transform) in layers.iter_mut() {
+        let position = layer.position(camera);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
@@ -1,7 +1,7 @@
 use std::ops::{DerefMut, Deref};
 use bevy::prelude::*;
 
-use crate::prelude::GameState;
+use crate::prelude::{DepthLane, GameState, GameTime};
 
 /// Represents the physics body
 #[derive(Debug, Clone, Component, PartialEq, Eq)]
@@ -68,6 +68,28 @@ pub struct Collider {
     pub half_extents: Vec2,
 }
 
+/// The distance between depth lanes below which bodies are in the same lane
+pub const LANE_TOLERANCE: f32 = 0.5;
+
+/// The distance checked around a body for touching colliders
+pub const CONTACT_SKIN: f32 = 2.0;
+
+/// The sides of a body that are touching other colliders
+#[derive(Debug, Default, Clone, Component)]
+pub struct Contacts {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl Contacts {
+    /// The direction of the wall the body is touching, -1 for left, 1 for right and 0 for none
+    pub fn wall_direction(&self) -> f32 {
+        self.right as i32 as f32 - self.left as i32 as f32
+    }
+}
+
 /// A bundle for a physics bundle
 #[derive(Debug, Clone, Bundle)]
 pub struct PhysicsBodyBundle {
@@ -99,6 +121,17 @@ impl Default for PhysicsBodyBundle {
 pub struct PhysicsConfig {
     pub enabled: bool,
     pub gravity: Vec2,
+    /// Only let the bodies in the same depth lane collide
+    pub lane_collisions: bool,
+}
+
+impl PhysicsConfig {
+    /// Check if bodies in the given lanes can collide
+    pub fn can_collide(&self, a: Option<&DepthLane>, b: Option<&DepthLane>) -> bool {
+        let a = a.map_or(0.0, |lane| lane.0);
+        let b = b.map_or(0.0, |lane| lane.0);
+        !self.lane_collisions || (a - b).abs() < LANE_TOLERANCE
+    }
 }
 
 impl Default for PhysicsConfig {
@@ -106,6 +139,7 @@ impl Default for PhysicsConfig {
         Self {
             enabled: true,
             gravity: Vec2::new(0f32, -98.1f32),
+            lane_collisions: false,
         }
     }
 }
@@ -134,6 +168,11 @@ impl Plugin for PhysicsPlugin {
                     apply_friction
                         .label("apply_friction")
                         .after("apply_velocity"),
+                )
+                .with_system(
+                    update_contacts
+                        .label("update_contacts")
+                        .after("apply_friction"),
                 ),
         );
     }
@@ -141,14 +180,26 @@ impl Plugin for PhysicsPlugin {
 
 /// Handle collisions between the bodies
 fn handle_collisions(
-    time: Res<Time>,
-    mut bodies: Query<(&mut Velocity, &Collider, &Transform, &PhysicsBody)>,
+    time: Res<GameTime>,
+    physics_conf: Res<PhysicsConfig>,
+    mut bodies: Query<(
+        &mut Velocity,
+        &Collider,
+        &Transform,
+        &PhysicsBody,
+        Option<&DepthLane>,
+    )>,
 ) {
     let mut iter = bodies.iter_combinations_mut();
     while let Some(
-        [(mut velocity_a, collider_a, transform_a, _), (mut velocity_b, collider_b, transform_b, _)],
+        [(mut velocity_a, collider_a, transform_a, _, lane_a), (mut velocity_b, collider_b, transform_b, _, lane_b)],
     ) = iter.fetch_next()
     {
+        // Skip the bodies in other lanes
+        if !physics_conf.can_collide(lane_a, lane_b) {
+            continue;
+        }
+
         // Calulate the next positions for body a
         let next_x = transform_a.translation.x + velocity_a.x * time.delta_seconds();
         let next_y = transform_a.translation.y + velocity_a.y * time.delta_seconds();
@@ -193,7 +244,7 @@ fn handle_collisions(
 
 /// Apply the gravity to the body
 fn apply_gravity(
-    time: Res<Time>,
+    time: Res<GameTime>,
     physics_conf: Res<PhysicsConfig>,
     mut bodies: Query<(&mut Velocity, &PhysicsBody, &GravityScale)>,
 ) {
@@ -205,7 +256,7 @@ fn apply_gravity(
 }
 
 /// Applies the velocity to the bodies
-fn apply_velocity(time: Res<Time>, mut bodies: Query<(&mut Transform, &PhysicsBody, &Velocity)>) {
+fn apply_velocity(time: Res<GameTime>, mut bodies: Query<(&mut Transform, &PhysicsBody, &Velocity)>) {
     for (mut transform, body, velocity) in bodies.iter_mut() {
         if *body == PhysicsBody::Dynamic {
             transform.translation.x += velocity.x * time.delta_seconds();
@@ -215,7 +266,7 @@ fn apply_velocity(time: Res<Time>, mut bodies: Query<(&mut Transform, &PhysicsBo
 }
 
 /// Applies the friction to the bodies
-fn apply_friction(time: Res<Time>, mut bodies: Query<(&mut Velocity, &PhysicsBody, &Friction)>) {
+fn apply_friction(time: Res<GameTime>, mut bodies: Query<(&mut Velocity, &PhysicsBody, &Friction)>) {
     for (mut velocity, body, friction) in bodies.iter_mut() {
         if *body == PhysicsBody::Dynamic {
             velocity.x += (friction.x * time.delta_seconds()) * (0f32 - velocity.x);
@@ -223,3 +274,39 @@ fn apply_friction(time: Res<Time>, mut bodies: Query<(&mut Velocity, &PhysicsBod
         }
     }
 }
+
+/// Update the contacts of the bodies by probing around their colliders
+fn update_contacts(
+    physics_conf: Res<PhysicsConfig>,
+    colliders: Query<(Entity, &Transform, &Collider, Option<&DepthLane>), With<PhysicsBody>>,
+    mut bodies: Query<(Entity, &Transform, &Collider, Option<&DepthLane>, &mut Contacts)>,
+) {
+    for (entity, transform, collider, lane, mut contacts) in bodies.iter_mut() {
+        *contacts = Contacts::default();
+
+        for (other, other_transform, other_collider, other_lane) in colliders.iter() {
+            if other == entity || !physics_conf.can_collide(lane, other_lane) {
+                continue;
+            }
+
+            let distance = (other_transform.translation - transform.translation).truncate();
+            let reach = collider.half_extents + other_collider.half_extents;
+            let overlaps_x = distance.x.abs() < reach.x;
+            let overlaps_y = distance.y.abs() < reach.y;
+
+            // Check for colliders within the skin on each side
+            if overlaps_x && distance.y > 0.0 && distance.y < reach.y + CONTACT_SKIN {
+                contacts.up = true;
+            }
+            if overlaps_x && distance.y < 0.0 && -distance.y < reach.y + CONTACT_SKIN {
+                contacts.down = true;
+            }
+            if overlaps_y && distance.x > 0.0 && distance.x < reach.x + CONTACT_SKIN {
+                contacts.right = true;
+            }
+            if overlaps_y && distance.x < 0.0 && -distance.x < reach.x + CONTACT_SKIN {
+                contacts.left = true;
+            }
+        }
+    }
+}
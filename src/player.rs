@@ -1,34 +1,209 @@
-use crate::prelude::{Collider, Friction, GameState, GravityScale, PhysicsBody, Velocity};
+use crate::prelude::{
+    Action, ActionCommand, ActionCommandEvent, ActionState, CameraShake, Collider, ComboEvent,
+    Contacts, DepthLane, Facing, Friction, GameState, GameTime, GamepadAssignment, Grade,
+    GravityScale, Health, Hitbox, Hurtbox, InputBuffer, Invulnerability, KeyboardAssignment,
+    LaneMover, MachineState, PhysicsBody, PhysicsConfig, PlayerTuning, Respawn, StateEnterEvent,
+    StateExitEvent, StateMachine, StateMachinePlugin, TimingWindow, Velocity, CONTACT_SKIN,
+    POWER_STRIKE,
+};
 use bevy::prelude::*;
 
-// The player constants
-const PLAYER_WALK_ACCEL: f32 = 12.0;
-const PLAYER_RUN_ACCEL: f32 = 17.0;
-const PLAYER_JUMP_FORCE: f32 = 135.0;
-
 /// A bundle holding the components for the player
 #[derive(Debug, Clone, Bundle)]
 pub struct PlayerBundle {
-    pub state: PlayerState,
+    pub index: PlayerIndex,
+    pub state: StateMachine<PlayerState>,
     pub input_state: PlayerInputState,
+    pub actions: ActionState,
+    pub input_buffer: InputBuffer,
+    pub gamepad: GamepadAssignment,
     pub transform: Transform,
     pub body: PhysicsBody,
     pub velocity: Velocity,
     pub friction: Friction,
     pub gravity_scale: GravityScale,
     pub collider: Collider,
+    pub contacts: Contacts,
+    pub input_lock: PlayerInputLock,
+    pub abilities: PlayerAbilities,
+    pub traversal: PlayerTraversal,
+    pub attack: PlayerAttack,
+    pub facing: Facing,
+    pub health: Health,
+    pub invulnerability: Invulnerability,
+    pub hurtbox: Hurtbox,
+    pub respawn: Respawn,
+    pub lane: DepthLane,
+    pub lane_mover: LaneMover,
 }
 
 /// Represents the player state
-#[derive(Debug, Clone, Copy, Component)]
+/// Runs on a [`StateMachine`], with the grounded and airborne states grouped under a parent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PlayerState {
+    Grounded,
+    Airborne,
     Idle,
     Walking,
     Falling,
     Jumping,
+    Skid,
+    WallSlide,
+    WallJump,
+    DoubleJump,
+    Dash,
+    GroundPound,
+    Crouch,
+    LedgeHang,
+    Climbing,
     Attack,
 }
 
+impl MachineState for PlayerState {
+    fn parent(&self) -> Option<Self> {
+        match self {
+            PlayerState::Idle | PlayerState::Walking | PlayerState::Skid | PlayerState::Crouch => {
+                Some(PlayerState::Grounded)
+            }
+            PlayerState::Falling
+            | PlayerState::Jumping
+            | PlayerState::DoubleJump
+            | PlayerState::WallJump
+            | PlayerState::GroundPound => Some(PlayerState::Airborne),
+            _ => None,
+        }
+    }
+
+    fn can_transition(&self, next: &Self) -> bool {
+        // The parent states only group their children and can't be entered directly
+        !matches!(next, PlayerState::Grounded | PlayerState::Airborne)
+    }
+}
+
+/// The abilities of the player and their runtime state
+/// The abilities are unlocked over the course of the story
+#[derive(Debug, Default, Clone, Component)]
+pub struct PlayerAbilities {
+    // The unlock flag for the double jump
+    pub double_jump_unlocked: bool,
+    // The unlock flag for the dash
+    pub dash_unlocked: bool,
+    // The unlock flag for the ground pound
+    pub ground_pound_unlocked: bool,
+    // The flag for if the double jump was used since the player was last grounded
+    pub has_double_jumped: bool,
+    // The direction of the current dash
+    pub dash_direction: f32,
+    // The flag for if the player is dashing, the dash ends after the dash duration in the dash state
+    pub is_dashing: bool,
+    // The remaining time until the player can dash again
+    pub dash_cooldown: f32,
+    // The remaining time the player is invulnerable from dashing
+    pub dash_invulnerability: f32,
+    // The flag for if the player is ground pounding
+    pub is_ground_pounding: bool,
+}
+
+impl PlayerAbilities {
+    /// Check if the abilities currently make the player invulnerable
+    pub fn is_invulnerable(&self) -> bool {
+        self.dash_invulnerability > 0.0
+    }
+
+    /// Check if the ability is unlocked
+    pub fn is_unlocked(&self, ability: Ability) -> bool {
+        match ability {
+            Ability::DoubleJump => self.double_jump_unlocked,
+            Ability::Dash => self.dash_unlocked,
+            Ability::GroundPound => self.ground_pound_unlocked,
+        }
+    }
+
+    /// Unlock the ability
+    pub fn unlock(&mut self, ability: Ability) {
+        match ability {
+            Ability::DoubleJump => self.double_jump_unlocked = true,
+            Ability::Dash => self.dash_unlocked = true,
+            Ability::GroundPound => self.ground_pound_unlocked = true,
+        }
+    }
+}
+
+/// An ability that is unlocked over the course of the story
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ability {
+    DoubleJump,
+    Dash,
+    GroundPound,
+}
+
+/// Unlocks an ability for the player that touches it, and is removed after
+/// Uses the [`Collider`] of the entity as the region
+#[derive(Debug, Clone, Component)]
+pub struct AbilityPickup(pub Ability);
+
+/// Sent to unlock an ability for a player, by a pickup or the story
+#[derive(Debug, Clone)]
+pub struct UnlockAbilityEvent {
+    pub player: Entity,
+    pub ability: Ability,
+}
+
+/// Marks a block that breaks when the player ground pounds it
+#[derive(Debug, Default, Clone, Component)]
+pub struct Breakable;
+
+/// Marks a region the player can climb, like a ladder or vines
+/// Uses the [`Collider`] of the entity as the region
+#[derive(Debug, Default, Clone, Component)]
+pub struct Climbable;
+
+/// The state of the players crouching, ledge grabbing and climbing
+#[derive(Debug, Default, Clone, Component)]
+pub struct PlayerTraversal {
+    // The flag for if the player is crouching
+    pub is_crouching: bool,
+    // The half extents of the collider when standing
+    pub standing_half_extents: Vec2,
+    // The corner of the ledge the player is hanging from
+    pub ledge: Option<Vec2>,
+    // The direction of the ledge the player is hanging from
+    pub ledge_direction: f32,
+    // The remaining time until the player can grab a ledge again
+    pub ledge_cooldown: f32,
+    // The flag for if the player is climbing
+    pub is_climbing: bool,
+    // The remaining time until the player can grab a climbable again
+    pub climb_cooldown: f32,
+}
+
+/// The state of the players attack
+#[derive(Debug, Default, Clone, Component)]
+pub struct PlayerAttack {
+    // The flag for if the player is attacking, the attack runs on the time in the attack state
+    pub is_attacking: bool,
+    // The hitbox of the attack while it is active
+    pub hitbox: Option<Entity>,
+    // The flag for if the attack finished the power strike combo
+    pub is_power_strike: bool,
+}
+
+impl PlayerAttack {
+    /// Start a new attack
+    pub fn start(&mut self) {
+        self.is_attacking = true;
+        self.is_power_strike = false;
+    }
+}
+
+/// The index of a player in local multiplayer, starting at 0 for the first player
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Component)]
+pub struct PlayerIndex(pub usize);
+
+/// The time in seconds the horizontal input of the player is ignored for
+#[derive(Debug, Default, Clone, Component)]
+pub struct PlayerInputLock(pub f32);
+
 /// The controller the player is currently using
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PlayerControllerState {
@@ -43,25 +218,55 @@ pub struct PlayerInputState {
     pub controller: PlayerControllerState,
     // The input on the x-axis
     pub xmove: f32,
+    // The input on the y-axis
+    pub ymove: f32,
     // The flag for if the player is sprinting
     pub is_sprinting: bool,
     // The flag for if the player is jumping
     pub is_jumping: bool,
+    // The flag for if the player is holding the jump button
+    pub is_holding_jump: bool,
+    // The flag for if the player released the jump button this frame
+    pub is_jump_released: bool,
+    // The flag for if the player is trying to dash
+    pub is_dashing: bool,
+    // The flag for if the player is trying to ground pound
+    pub is_ground_pounding: bool,
+    // The flag for if the player is trying to attack
+    pub is_attacking: bool,
 }
 
 impl Default for PlayerBundle {
     fn default() -> Self {
         Self {
-            state: PlayerState::Idle,
+            index: Default::default(),
+            state: StateMachine::new(PlayerState::Idle),
             input_state: Default::default(),
+            actions: Default::default(),
+            input_buffer: Default::default(),
+            gamepad: Default::default(),
             transform: Default::default(),
             body: PhysicsBody::Dynamic,
             velocity: Vec2::ZERO.into(),
             friction: Vec2::ZERO.into(),
             collider: Collider {
-                half_extents: Vec2::ZERO,
+                half_extents: Vec2::new(12.0, 12.0),
             },
             gravity_scale: GravityScale(1.0),
+            contacts: Default::default(),
+            input_lock: Default::default(),
+            abilities: Default::default(),
+            traversal: Default::default(),
+            attack: Default::default(),
+            facing: Default::default(),
+            health: Health::new(10.0),
+            invulnerability: Invulnerability::new(1.0),
+            hurtbox: Hurtbox {
+                half_extents: Vec2::new(12.0, 12.0),
+            },
+            respawn: Default::default(),
+            lane: Default::default(),
+            lane_mover: Default::default(),
         }
     }
 }
@@ -71,8 +276,14 @@ impl Default for PlayerInputState {
         Self {
             controller: PlayerControllerState::Keyboard,
             xmove: 0.0,
+            ymove: 0.0,
             is_sprinting: false,
             is_jumping: false,
+            is_holding_jump: false,
+            is_jump_released: false,
+            is_dashing: false,
+            is_ground_pounding: false,
+            is_attacking: false,
         }
     }
 }
@@ -82,150 +293,949 @@ pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
+        app.add_plugin(StateMachinePlugin::<PlayerState>::default());
+        app.add_event::<UnlockAbilityEvent>();
+
         app.add_system_set(
             SystemSet::on_update(GameState::Gameplay)
                 .with_system(
                     update_controller_state
                         .label("update_controller_state")
-                        .after("apply_friction"),
+                        .after("update_contacts")
+                        .after("assign_gamepads"),
                 )
                 .with_system(
                     update_input_state
                         .label("update_input_state")
-                        .after("update_controller_state"),
+                        .after("update_action_states")
+                        .after("replay_inputs"),
+                )
+                .with_system(
+                    update_facing
+                        .label("update_facing")
+                        .after("update_input_state"),
+                )
+                .with_system(
+                    collect_ability_pickups
+                        .label("collect_ability_pickups")
+                        .after("update_contacts"),
+                )
+                .with_system(
+                    unlock_abilities
+                        .label("unlock_abilities")
+                        .after("collect_ability_pickups"),
+                )
+                .with_system(
+                    handle_state_events
+                        .label("handle_state_events")
+                        .after("update_facing"),
+                )
+                .with_system(
+                    update_abilities
+                        .label("update_abilities")
+                        .after("handle_state_events")
+                        .after("unlock_abilities"),
+                )
+                .with_system(
+                    handle_ground_pound
+                        .label("handle_ground_pound")
+                        .after("update_abilities"),
+                )
+                .with_system(
+                    handle_crouch
+                        .label("handle_crouch")
+                        .after("handle_ground_pound"),
+                )
+                .with_system(
+                    handle_climbing
+                        .label("handle_climbing")
+                        .after("handle_crouch"),
+                )
+                .with_system(
+                    handle_ledge_grab
+                        .label("handle_ledge_grab")
+                        .after("handle_climbing"),
                 )
                 .with_system(
                     update_state
                         .label("update_state")
-                        .after("update_input_state"),
+                        .after("handle_ledge_grab"),
                 )
-                .with_system(handle_state.label("handle_state").after("update_state")),
+                .with_system(handle_state.label("handle_state").after("update_state"))
+                .with_system(
+                    handle_jump_arc
+                        .label("handle_jump_arc")
+                        .after("handle_state"),
+                )
+                .with_system(
+                    handle_ground_pound_jump
+                        .label("handle_ground_pound_jump")
+                        .after("grade_action_commands")
+                        .after("handle_state")
+                        .before("handle_jump_arc"),
+                )
+                .with_system(
+                    handle_combos
+                        .label("handle_combos")
+                        .after("detect_combos")
+                        .after("handle_state"),
+                )
+                .with_system(
+                    handle_attack
+                        .label("handle_attack")
+                        .after("handle_state")
+                        .after("handle_combos"),
+                ),
         );
     }
 }
 
 /// Update the players state
-fn update_state(mut states: Query<(&mut PlayerState, &Velocity, &PlayerInputState)>) {
+fn update_state(
+    tuning: Res<PlayerTuning>,
+    mut states: Query<(
+        &mut StateMachine<PlayerState>,
+        &Velocity,
+        &PlayerInputState,
+        &Contacts,
+        &PlayerInputLock,
+        &PlayerAbilities,
+        &PlayerTraversal,
+        &PlayerAttack,
+    )>,
+) {
     // Loop over the player entities
-    for (mut state, velocity, input) in states.iter_mut() {
-        if velocity.0.y > 0.0 {
-            *state = PlayerState::Jumping;
-            continue;
-        }
-        if velocity.0.y < 0.0 {
-            *state = PlayerState::Falling;
-            continue;
-        }
-        if input.xmove != 0.0 {
-            *state = PlayerState::Walking;
-            continue;
-        }
+    for (mut machine, velocity, input, contacts, lock, abilities, traversal, attack) in
+        states.iter_mut()
+    {
+        let next = if attack.is_attacking {
+            PlayerState::Attack
+        } else if abilities.is_dashing {
+            PlayerState::Dash
+        } else if abilities.is_ground_pounding {
+            PlayerState::GroundPound
+        } else if traversal.ledge.is_some() {
+            PlayerState::LedgeHang
+        } else if traversal.is_climbing {
+            PlayerState::Climbing
+        } else if lock.0 > 0.0 {
+            // Stay in the wall jump until the input is unlocked
+            PlayerState::WallJump
+        } else if velocity.0.y < 0.0
+            && !contacts.down
+            && input.xmove * contacts.wall_direction() > 0.0
+        {
+            // Slide when falling while pressing into a wall
+            PlayerState::WallSlide
+        } else if velocity.0.y > 0.0 && abilities.has_double_jumped {
+            PlayerState::DoubleJump
+        } else if velocity.0.y > 0.0 {
+            PlayerState::Jumping
+        } else if velocity.0.y < 0.0 {
+            PlayerState::Falling
+        } else if traversal.is_crouching {
+            PlayerState::Crouch
+        } else if input.xmove * velocity.0.x < 0.0 && velocity.0.x.abs() > tuning.skid_threshold {
+            PlayerState::Skid
+        } else if input.xmove != 0.0 {
+            PlayerState::Walking
+        } else {
+            PlayerState::Idle
+        };
 
-        *state = PlayerState::Idle;
+        machine.transition(next);
     }
 }
 
 /// Update the player controller state
 fn update_controller_state(
     keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
     gamepads: Res<Input<GamepadButton>>,
-    mut states: Query<&mut PlayerInputState>,
+    mut states: Query<(
+        &mut PlayerInputState,
+        &GamepadAssignment,
+        Option<&KeyboardAssignment>,
+    )>,
 ) {
-    for mut input in states.iter_mut() {
-        // Check for gamepad input
-        if gamepads.get_just_pressed().len() > 0 {
-            input.controller = PlayerControllerState::Gamepad(0);
+    for (mut input, assignment, keyboard) in states.iter_mut() {
+        // Check for input on the assigned gamepad
+        if let Some(gamepad) = assignment.gamepad {
+            if gamepads
+                .get_just_pressed()
+                .any(|button| button.0 == gamepad)
+            {
+                input.controller = PlayerControllerState::Gamepad(gamepad.0);
+            }
         }
-        // Check for keyboard input
-        if keys.get_just_pressed().len() > 0 {
+        // Check for keyboard and mouse input, only for the player the keyboard is bound to
+        if keyboard.is_some()
+            && (keys.get_just_pressed().len() > 0 || mouse.get_just_pressed().len() > 0)
+        {
             input.controller = PlayerControllerState::Keyboard;
         }
     }
 }
 
-/// Update the players input state
-fn update_input_state(
-    keys: Res<Input<KeyCode>>,
-    gamepad_input: Res<Input<GamepadButton>>,
-    gamepad_axes: Res<Axis<GamepadAxis>>,
-    mut states: Query<&mut PlayerInputState>,
-) {
-    for mut input in states.iter_mut() {
-        match input.controller {
-            PlayerControllerState::Gamepad(id) => {
-                // Get the gamepad
-                let gamepad = Gamepad(id as usize);
-                // Check if the player is sprinting
-                input.is_sprinting =
-                    gamepad_input.pressed(GamepadButton(gamepad, GamepadButtonType::West));
-                // Check if the player is trying to jump
-                input.is_jumping =
-                    gamepad_input.just_pressed(GamepadButton(gamepad, GamepadButtonType::South));
-                // Update the horizontal input
-                input.xmove = gamepad_axes
-                    .get(GamepadAxis(gamepad, GamepadAxisType::LeftStickX))
-                    .unwrap();
-            }
-            PlayerControllerState::Keyboard => {
-                // Check if the player is sprinting
-                input.is_sprinting = keys.pressed(KeyCode::LShift);
-                // Check if the player is trying to jump
-                input.is_jumping = keys.just_pressed(KeyCode::Space);
-                // Update the horizontal input
-                input.xmove = -(keys.pressed(KeyCode::A) as i32 as f32)
-                    + keys.pressed(KeyCode::D) as i32 as f32;
-            }
-        };
+/// Update the players input state from their actions
+fn update_input_state(mut states: Query<(&mut PlayerInputState, &ActionState)>) {
+    for (mut input, actions) in states.iter_mut() {
+        // Check if the player is sprinting
+        input.is_sprinting = actions.pressed(Action::Run);
+        // Check if the player is trying to jump
+        input.is_jumping = actions.just_pressed(Action::Jump);
+        input.is_holding_jump = actions.pressed(Action::Jump);
+        input.is_jump_released = actions.just_released(Action::Jump);
+        // Check if the player is trying to attack
+        input.is_attacking = actions.just_pressed(Action::Attack);
+        // Check if the player is trying to use an ability
+        input.is_dashing = actions.just_pressed(Action::Dash);
+        input.is_ground_pounding = actions.just_pressed(Action::GroundPound);
+        // Update the horizontal input
+        input.xmove = actions.value(Action::MoveRight) - actions.value(Action::MoveLeft);
+        // Update the vertical input
+        input.ymove = actions.value(Action::MoveUp) - actions.value(Action::MoveDown);
     }
 }
 
 /// Handle the players state
 fn handle_state(
-    time: Res<Time>,
-    mut players: Query<(&mut Velocity, &PlayerState, &PlayerInputState)>,
+    time: Res<GameTime>,
+    tuning: Res<PlayerTuning>,
+    mut players: Query<(
+        &mut Velocity,
+        &mut PlayerInputLock,
+        &StateMachine<PlayerState>,
+        &PlayerInputState,
+        &Contacts,
+        &mut PlayerAbilities,
+        &mut PlayerTraversal,
+        &mut PlayerAttack,
+    )>,
 ) {
-    for (mut velocity, state, input) in players.iter_mut() {
-        match state {
-            PlayerState::Idle => {
+    for (
+        mut velocity,
+        mut lock,
+        machine,
+        input,
+        contacts,
+        mut abilities,
+        mut traversal,
+        mut attack,
+    ) in players.iter_mut()
+    {
+        lock.0 = (lock.0 - time.delta_seconds()).max(0.0);
+
+        match machine.current() {
+            PlayerState::Idle | PlayerState::Walking | PlayerState::Skid => {
+                if input.is_attacking {
+                    attack.start();
+                    continue;
+                }
+                if try_dash(&mut velocity, &mut abilities, input, &tuning) {
+                    continue;
+                }
                 if input.is_jumping && velocity.0.y == 0.0 {
-                    velocity.0.y += PLAYER_JUMP_FORCE;
+                    velocity.0.y = tuning.jump_velocity();
                 }
+
+                velocity.0.x =
+                    horizontal_velocity(velocity.0.x, input, &tuning, false, time.delta_seconds());
             }
-            PlayerState::Walking => {
-                if input.is_jumping && velocity.0.y == 0.0 {
-                    velocity.0.y += PLAYER_JUMP_FORCE;
+            PlayerState::Falling | PlayerState::Jumping | PlayerState::DoubleJump => {
+                if input.is_attacking {
+                    attack.start();
+                    continue;
+                }
+                if try_dash(&mut velocity, &mut abilities, input, &tuning) {
+                    continue;
+                }
+                // Start a ground pound
+                if input.is_ground_pounding && abilities.ground_pound_unlocked {
+                    abilities.is_ground_pounding = true;
+                    velocity.0 = Vec2::ZERO;
+                    continue;
+                }
+                // Jump again in mid-air
+                if input.is_jumping
+                    && abilities.double_jump_unlocked
+                    && !abilities.has_double_jumped
+                {
+                    abilities.has_double_jumped = true;
+                    velocity.0.y = tuning.double_jump_velocity();
                 }
 
-                velocity.0.x += input.xmove
-                    * if input.is_sprinting {
-                        PLAYER_RUN_ACCEL
-                    } else {
-                        PLAYER_WALK_ACCEL
-                    }
-                    * time.delta_seconds()
-                    * 100.0;
-            }
-            PlayerState::Falling => {
-                velocity.0.x += input.xmove
-                    * if input.is_sprinting {
-                        PLAYER_RUN_ACCEL
-                    } else {
-                        PLAYER_WALK_ACCEL
-                    }
-                    * time.delta_seconds()
-                    * 100.0;
-            }
-            PlayerState::Jumping => {
-                velocity.0.x += input.xmove
-                    * if input.is_sprinting {
-                        PLAYER_RUN_ACCEL
+                velocity.0.x =
+                    horizontal_velocity(velocity.0.x, input, &tuning, true, time.delta_seconds());
+            }
+            PlayerState::Dash => {
+                velocity.0 = Vec2::new(abilities.dash_direction * tuning.dash_speed, 0.0);
+            }
+            PlayerState::GroundPound => {
+                // Hang in the air for a moment before slamming down
+                velocity.0 = if machine.time_in_state() < tuning.ground_pound_hang {
+                    Vec2::ZERO
+                } else {
+                    Vec2::new(0.0, -tuning.ground_pound_speed)
+                };
+            }
+            PlayerState::Crouch => {
+                velocity.0.x =
+                    horizontal_velocity(velocity.0.x, input, &tuning, false, time.delta_seconds())
+                        .clamp(-tuning.crouch_speed, tuning.crouch_speed);
+            }
+            PlayerState::LedgeHang => {
+                velocity.0 = Vec2::ZERO;
+            }
+            PlayerState::Climbing => {
+                // Jump off the climbable
+                if input.is_jumping {
+                    traversal.is_climbing = false;
+                    traversal.climb_cooldown = tuning.climb_regrab_cooldown;
+                    velocity.0.y = tuning.jump_velocity();
+                    continue;
+                }
+
+                velocity.0 = Vec2::new(input.xmove, input.ymove) * tuning.climb_speed;
+            }
+            PlayerState::WallSlide => {
+                // Kick off away from the wall
+                if input.is_jumping {
+                    traversal.climb_cooldown = tuning.climb_regrab_cooldown;
+                    velocity.0.x = -contacts.wall_direction() * tuning.wall_jump_horizontal_speed;
+                    velocity.0.y = tuning.wall_jump_vertical_speed;
+                    lock.0 = tuning.wall_jump_lock_time;
+                    continue;
+                }
+
+                velocity.0.x =
+                    horizontal_velocity(velocity.0.x, input, &tuning, true, time.delta_seconds());
+                velocity.0.y = velocity.0.y.max(-tuning.wall_slide_speed);
+            }
+            PlayerState::WallJump => {
+                // The horizontal input is ignored until the lock runs out
+                if lock.0 <= 0.0 {
+                    velocity.0.x = horizontal_velocity(
+                        velocity.0.x,
+                        input,
+                        &tuning,
+                        true,
+                        time.delta_seconds(),
+                    );
+                }
+            }
+            // The parent states are never the current state
+            PlayerState::Grounded | PlayerState::Airborne => {}
+            PlayerState::Attack => {
+                // The swing roots the player in place
+                velocity.0.x = move_towards(velocity.0.x, 0.0, tuning.decel * time.delta_seconds());
+            }
+        };
+    }
+}
+
+/// Calculate the next horizontal velocity for the input
+/// Accelerates towards the max speed, decelerates when there is no input
+/// and uses the skid deceleration when turning around
+fn horizontal_velocity(
+    current: f32,
+    input: &PlayerInputState,
+    tuning: &PlayerTuning,
+    airborne: bool,
+    delta: f32,
+) -> f32 {
+    let (max_speed, accel) = if input.is_sprinting {
+        (tuning.max_run_speed, tuning.run_accel)
+    } else {
+        (tuning.max_walk_speed, tuning.walk_accel)
+    };
+    let (accel_multiplier, decel_multiplier) = if airborne {
+        (tuning.air_accel_multiplier, tuning.air_decel_multiplier)
+    } else {
+        (1.0, 1.0)
+    };
+
+    let target = input.xmove.clamp(-1.0, 1.0) * max_speed;
+    let rate = if input.xmove == 0.0 || current.abs() > max_speed {
+        // Slow down when letting go or when above the cap, e.g. after releasing run
+        tuning.decel * decel_multiplier
+    } else if current * input.xmove < 0.0 {
+        // Turning around
+        tuning.skid_decel * decel_multiplier
+    } else {
+        accel * accel_multiplier
+    };
+
+    move_towards(current, target, rate * delta)
+}
+
+/// Move a value towards the target without overshooting
+fn move_towards(current: f32, target: f32, max_delta: f32) -> f32 {
+    if (target - current).abs() <= max_delta {
+        target
+    } else {
+        current + (target - current).signum() * max_delta
+    }
+}
+
+/// Turn the players towards their horizontal input
+fn update_facing(mut players: Query<(&mut Facing, &PlayerInputState, &PlayerAttack)>) {
+    for (mut facing, input, attack) in players.iter_mut() {
+        // Keep facing the same way for the whole attack
+        if input.xmove != 0.0 && !attack.is_attacking {
+            facing.0 = input.xmove.signum();
+        }
+    }
+}
+
+/// Start a dash if the player is trying to and it is available
+/// Returns true if the player started dashing
+fn try_dash(
+    velocity: &mut Velocity,
+    abilities: &mut PlayerAbilities,
+    input: &PlayerInputState,
+    tuning: &PlayerTuning,
+) -> bool {
+    if !input.is_dashing
+        || !abilities.dash_unlocked
+        || abilities.is_dashing
+        || abilities.dash_cooldown > 0.0
+    {
+        return false;
+    }
+
+    // Dash in the direction of the input, or the direction the player is moving in
+    abilities.dash_direction = if input.xmove != 0.0 {
+        input.xmove.signum()
+    } else if velocity.0.x != 0.0 {
+        velocity.0.x.signum()
+    } else {
+        1.0
+    };
+    abilities.is_dashing = true;
+    velocity.0 = Vec2::new(abilities.dash_direction * tuning.dash_speed, 0.0);
+    true
+}
+
+/// Unlock the abilities of the pickups the players touch
+fn collect_ability_pickups(
+    mut commands: Commands,
+    mut unlock_events: EventWriter<UnlockAbilityEvent>,
+    players: Query<(Entity, &Transform, &Collider), With<PlayerAbilities>>,
+    pickups: Query<(Entity, &Transform, &Collider, &AbilityPickup)>,
+) {
+    for (pickup, pickup_transform, pickup_collider, ability) in pickups.iter() {
+        let touched_by = players.iter().find(|(_, transform, collider)| {
+            is_blocked(
+                transform.translation.truncate(),
+                collider.half_extents,
+                [(pickup_transform, pickup_collider)],
+            )
+        });
+
+        if let Some((player, _, _)) = touched_by {
+            unlock_events.send(UnlockAbilityEvent {
+                player,
+                ability: ability.0,
+            });
+            commands.entity(pickup).despawn();
+        }
+    }
+}
+
+/// Unlock the abilities of the unlock events
+fn unlock_abilities(
+    mut unlock_events: EventReader<UnlockAbilityEvent>,
+    mut players: Query<&mut PlayerAbilities>,
+) {
+    for event in unlock_events.iter() {
+        if let Ok(mut abilities) = players.get_mut(event.player) {
+            if !abilities.is_unlocked(event.ability) {
+                info!("Unlocked {:?}", event.ability);
+                abilities.unlock(event.ability);
+            }
+        }
+    }
+}
+
+/// Start the timers of the states the players enter and clean up after the states they exit
+fn handle_state_events(
+    mut commands: Commands,
+    tuning: Res<PlayerTuning>,
+    mut enter_events: EventReader<StateEnterEvent<PlayerState>>,
+    mut exit_events: EventReader<StateExitEvent<PlayerState>>,
+    mut players: Query<(&mut PlayerAbilities, &mut PlayerAttack)>,
+) {
+    for event in enter_events.iter() {
+        if event.state != PlayerState::Dash {
+            continue;
+        }
+        if let Ok((mut abilities, _)) = players.get_mut(event.entity) {
+            abilities.dash_cooldown = tuning.dash_cooldown;
+            abilities.dash_invulnerability = tuning.dash_invulnerability;
+        }
+    }
+
+    for event in exit_events.iter() {
+        if event.state != PlayerState::Attack {
+            continue;
+        }
+        // Don't leave the hitbox behind when the attack ends during the active frames
+        if let Ok((_, mut attack)) = players.get_mut(event.entity) {
+            if let Some(hitbox) = attack.hitbox.take() {
+                commands.entity(hitbox).despawn();
+            }
+        }
+    }
+}
+
+/// Tick the ability timers, end the dashes and refresh the double jump when grounded
+fn update_abilities(
+    time: Res<GameTime>,
+    tuning: Res<PlayerTuning>,
+    mut players: Query<(&mut PlayerAbilities, &Contacts, &StateMachine<PlayerState>)>,
+) {
+    for (mut abilities, contacts, machine) in players.iter_mut() {
+        if machine.current() == PlayerState::Dash && machine.time_in_state() >= tuning.dash_duration
+        {
+            abilities.is_dashing = false;
+        }
+        abilities.dash_cooldown = (abilities.dash_cooldown - time.delta_seconds()).max(0.0);
+        abilities.dash_invulnerability =
+            (abilities.dash_invulnerability - time.delta_seconds()).max(0.0);
+
+        if contacts.down || machine.current() == PlayerState::WallSlide {
+            abilities.has_double_jumped = false;
+        }
+    }
+}
+
+/// Break the blocks below ground pounding players, or end the ground pound when they land
+/// Landing starts an action command to jump right away
+fn handle_ground_pound(
+    mut commands: Commands,
+    physics_conf: Res<PhysicsConfig>,
+    mut shake_events: EventWriter<CameraShake>,
+    mut players: Query<(
+        Entity,
+        &Transform,
+        &Collider,
+        &mut Contacts,
+        &mut PlayerAbilities,
+        &InputBuffer,
+        Option<&DepthLane>,
+    )>,
+    breakables: Query<(Entity, &Transform, &Collider, Option<&DepthLane>), With<Breakable>>,
+) {
+    for (player, transform, collider, mut contacts, mut abilities, buffer, lane) in
+        players.iter_mut()
+    {
+        if !abilities.is_ground_pounding || !contacts.down {
+            continue;
+        }
+
+        let mut broke_block = false;
+        for (entity, block_transform, block_collider, block_lane) in breakables.iter() {
+            if !physics_conf.can_collide(lane, block_lane) {
+                continue;
+            }
+
+            let distance = (block_transform.translation - transform.translation).truncate();
+            let reach = collider.half_extents + block_collider.half_extents;
+
+            // Only break the blocks the player is landing on
+            if distance.x.abs() < reach.x
+                && distance.y < 0.0
+                && -distance.y < reach.y + CONTACT_SKIN
+            {
+                commands.entity(entity).despawn();
+                broke_block = true;
+            }
+        }
+
+        if broke_block {
+            // Keep pounding through the broken blocks
+            contacts.down = false;
+            shake_events.send(CameraShake {
+                trauma: 0.3,
+                direction: Vec2::new(0.0, -1.0),
+            });
+        } else {
+            abilities.is_ground_pounding = false;
+            shake_events.send(CameraShake {
+                trauma: 0.5,
+                direction: Vec2::new(0.0, -1.0),
+            });
+            commands.entity(player).insert(ActionCommand::new(
+                buffer,
+                Action::Jump,
+                0.0,
+                TimingWindow::default(),
+            ));
+        }
+    }
+}
+
+/// Boost the jumps that are timed with a ground pound landing
+fn handle_ground_pound_jump(
+    tuning: Res<PlayerTuning>,
+    mut command_events: EventReader<ActionCommandEvent>,
+    mut players: Query<(&mut Velocity, &Contacts)>,
+) {
+    for event in command_events.iter() {
+        if event.action != Action::Jump {
+            continue;
+        }
+        let multiplier = match event.grade {
+            Grade::Perfect => tuning.ground_pound_jump_multiplier,
+            Grade::Good => (1.0 + tuning.ground_pound_jump_multiplier) / 2.0,
+            Grade::Miss => continue,
+        };
+
+        if let Ok((mut velocity, contacts)) = players.get_mut(event.player) {
+            // Only boost the jump from the ground, not a double jump
+            if contacts.down && velocity.0.y > 0.0 {
+                velocity.0.y = tuning.jump_velocity() * multiplier;
+            }
+        }
+    }
+}
+
+/// Turn the attacks that finish the power strike combo into power strikes
+fn handle_combos(mut combo_events: EventReader<ComboEvent>, mut players: Query<&mut PlayerAttack>) {
+    for event in combo_events.iter() {
+        if event.combo != POWER_STRIKE {
+            continue;
+        }
+        if let Ok(mut attack) = players.get_mut(event.player) {
+            if attack.is_attacking {
+                attack.is_power_strike = true;
+            }
+        }
+    }
+}
+
+/// Run the attack timeline of the players on the time in the attack state
+/// Spawns the hitbox in front of the player when the active frames start
+/// and removes it again when they end
+fn handle_attack(
+    time: Res<GameTime>,
+    tuning: Res<PlayerTuning>,
+    mut commands: Commands,
+    mut players: Query<(
+        Entity,
+        &Transform,
+        &Facing,
+        &StateMachine<PlayerState>,
+        &mut PlayerAttack,
+    )>,
+) {
+    let active_start = tuning.attack_startup;
+    let active_end = active_start + tuning.attack_active;
+    let recovery_end = active_end + tuning.attack_recovery;
+
+    for (entity, transform, facing, machine, mut attack) in players.iter_mut() {
+        if !attack.is_attacking || machine.current() != PlayerState::Attack {
+            continue;
+        }
+        // The time in the attack at the start and the end of this frame
+        let previous = machine.time_in_state();
+        let now = previous + time.delta_seconds();
+
+        // Remove the hitbox after the active frames
+        // Checked with the time at the start of the frame, so the hitbox is kept for at least one frame
+        if previous >= active_end {
+            if let Some(hitbox) = attack.hitbox.take() {
+                commands.entity(hitbox).despawn();
+            }
+        }
+
+        // Spawn the hitbox for the active frames
+        // A long frame can step over the whole window, so check if the frame overlaps it
+        if previous < active_end && now >= active_start && attack.hitbox.is_none() {
+            let direction = Vec2::new(facing.0, 1.0);
+            let offset = tuning.attack_hitbox_offset * direction;
+            let hitbox = commands
+                .spawn()
+                .insert(Transform::from_translation(
+                    transform.translation + offset.extend(0.0),
+                ))
+                .insert(Hitbox {
+                    owner: entity,
+                    offset,
+                    half_extents: tuning.attack_hitbox_half_extents,
+                    damage: if attack.is_power_strike {
+                        tuning.attack_damage * tuning.power_strike_multiplier
                     } else {
-                        PLAYER_WALK_ACCEL
-                    }
-                    * time.delta_seconds()
-                    * 100.0;
+                        tuning.attack_damage
+                    },
+                    knockback: tuning.attack_knockback * direction,
+                    hits: Vec::new(),
+                })
+                .id();
+            attack.hitbox = Some(hitbox);
+        }
+
+        // End the attack after the recovery
+        if now >= recovery_end {
+            attack.is_attacking = false;
+        }
+    }
+}
+
+/// Check if a box overlaps any of the solid colliders
+fn is_blocked<'a>(
+    center: Vec2,
+    half_extents: Vec2,
+    solids: impl IntoIterator<Item = (&'a Transform, &'a Collider)>,
+) -> bool {
+    solids.into_iter().any(|(transform, collider)| {
+        let distance = (transform.translation.truncate() - center).abs();
+        let reach = half_extents + collider.half_extents;
+        distance.x < reach.x && distance.y < reach.y
+    })
+}
+
+/// The solid colliders a body in the lane can collide with
+fn solids_in_lane<'a>(
+    physics_conf: &'a PhysicsConfig,
+    lane: Option<&'a DepthLane>,
+    solids: impl IntoIterator<Item = (&'a Transform, &'a Collider, Option<&'a DepthLane>)>,
+) -> impl Iterator<Item = (&'a Transform, &'a Collider)> {
+    solids
+        .into_iter()
+        .filter(move |(_, _, other_lane)| physics_conf.can_collide(lane, *other_lane))
+        .map(|(transform, collider, _)| (transform, collider))
+}
+
+/// Shrink the collider of crouching players and stand them back up when there is room
+fn handle_crouch(
+    tuning: Res<PlayerTuning>,
+    physics_conf: Res<PhysicsConfig>,
+    mut players: Query<(
+        &mut Transform,
+        &mut Collider,
+        &mut PlayerTraversal,
+        &Contacts,
+        &PlayerInputState,
+        Option<&DepthLane>,
+    )>,
+    solids: Query<
+        (&Transform, &Collider, Option<&DepthLane>),
+        (With<PhysicsBody>, Without<PlayerTraversal>),
+    >,
+) {
+    for (mut transform, mut collider, mut traversal, contacts, input, lane) in players.iter_mut() {
+        let wants_crouch = contacts.down
+            && input.ymove < 0.0
+            && !traversal.is_climbing
+            && traversal.ledge.is_none();
+
+        if wants_crouch && !traversal.is_crouching {
+            // Shrink the collider while keeping the feet on the ground
+            traversal.is_crouching = true;
+            traversal.standing_half_extents = collider.half_extents;
+            collider.half_extents.y *= tuning.crouch_height_ratio;
+            transform.translation.y -= traversal.standing_half_extents.y - collider.half_extents.y;
+        } else if !wants_crouch && traversal.is_crouching {
+            // Only stand up when there is no ceiling in the way
+            let offset = traversal.standing_half_extents.y - collider.half_extents.y;
+            let standing_center = transform.translation.truncate() + Vec2::new(0.0, offset);
+            if is_blocked(
+                standing_center,
+                traversal.standing_half_extents,
+                solids_in_lane(&physics_conf, lane, solids.iter()),
+            ) {
+                continue;
+            }
+
+            traversal.is_crouching = false;
+            collider.half_extents = traversal.standing_half_extents;
+            transform.translation.y += offset;
+        }
+    }
+}
+
+/// Start and stop climbing when the player is inside of a climbable region
+/// Jumping off starts a cooldown, so the player doesn't grab the same climbable right away
+fn handle_climbing(
+    time: Res<GameTime>,
+    mut players: Query<(
+        &Transform,
+        &Collider,
+        &mut PlayerTraversal,
+        &PlayerInputState,
+    )>,
+    climbables: Query<(&Transform, &Collider), (With<Climbable>, Without<PlayerTraversal>)>,
+) {
+    for (transform, collider, mut traversal, input) in players.iter_mut() {
+        traversal.climb_cooldown = (traversal.climb_cooldown - time.delta_seconds()).max(0.0);
+
+        let in_climbable = is_blocked(
+            transform.translation.truncate(),
+            collider.half_extents,
+            climbables.iter(),
+        );
+
+        if !in_climbable {
+            traversal.is_climbing = false;
+        } else if input.ymove != 0.0
+            && !traversal.is_crouching
+            && traversal.ledge.is_none()
+            && traversal.climb_cooldown <= 0.0
+        {
+            traversal.is_climbing = true;
+        }
+    }
+}
+
+/// Grab ledges when falling next to them, and pull up or drop down from them
+fn handle_ledge_grab(
+    time: Res<GameTime>,
+    tuning: Res<PlayerTuning>,
+    physics_conf: Res<PhysicsConfig>,
+    mut players: Query<(
+        &mut Transform,
+        &mut Velocity,
+        &Collider,
+        &mut PlayerTraversal,
+        &Contacts,
+        &PlayerInputState,
+        Option<&DepthLane>,
+    )>,
+    solids: Query<
+        (&Transform, &Collider, Option<&DepthLane>),
+        (With<PhysicsBody>, Without<PlayerTraversal>),
+    >,
+) {
+    for (mut transform, mut velocity, collider, mut traversal, contacts, input, lane) in
+        players.iter_mut()
+    {
+        traversal.ledge_cooldown = (traversal.ledge_cooldown - time.delta_seconds()).max(0.0);
+
+        // Pull up onto the ledge or let go of it
+        // Letting go with jump leaves the player grounded for the jump in handle_state
+        if let Some(ledge) = traversal.ledge {
+            if input.ymove > 0.0 {
+                let top = ledge + collider.half_extents * Vec2::new(traversal.ledge_direction, 1.0);
+                transform.translation.x = top.x;
+                transform.translation.y = top.y;
+                traversal.ledge = None;
+            } else if input.is_jumping || input.ymove < 0.0 {
+                traversal.ledge = None;
+                traversal.ledge_cooldown = tuning.ledge_regrab_cooldown;
+            }
+            continue;
+        }
+
+        let direction = contacts.wall_direction();
+        if velocity.0.y > 0.0
+            || contacts.down
+            || direction == 0.0
+            || input.xmove * direction <= 0.0
+            || traversal.ledge_cooldown > 0.0
+            || traversal.is_crouching
+            || traversal.is_climbing
+        {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let top = position.y + collider.half_extents.y;
+        for (wall_transform, wall_collider) in solids_in_lane(&physics_conf, lane, solids.iter()) {
+            let wall = wall_transform.translation.truncate();
+            let distance = wall - position;
+            let reach = collider.half_extents + wall_collider.half_extents;
+
+            // Only check the walls on the side the player is pressing into
+            if distance.x * direction <= 0.0
+                || distance.x.abs() >= reach.x + CONTACT_SKIN
+                || distance.y.abs() >= reach.y
+            {
+                continue;
+            }
+
+            // The top of the wall has to be close to the top of the player
+            let wall_top = wall.y + wall_collider.half_extents.y;
+            if (wall_top - top).abs() > tuning.ledge_grab_reach {
+                continue;
+            }
+
+            // There has to be room to pull up onto the ledge
+            let corner = Vec2::new(wall.x - direction * wall_collider.half_extents.x, wall_top);
+            let standing_center =
+                corner + collider.half_extents * Vec2::new(direction, 1.0) + Vec2::new(0.0, 0.1);
+            if is_blocked(
+                standing_center,
+                collider.half_extents,
+                solids_in_lane(&physics_conf, lane, solids.iter()),
+            ) {
+                continue;
             }
-            PlayerState::Attack => {}
+
+            // Hang with the top of the player at the ledge
+            traversal.ledge = Some(corner);
+            traversal.ledge_direction = direction;
+            transform.translation.y = wall_top - collider.half_extents.y;
+            velocity.0 = Vec2::ZERO;
+            break;
+        }
+    }
+}
+
+/// Shape the jump arc of the players
+/// Cuts the upwards velocity when jump is released early,
+/// picks the gravity for rising or falling and caps the fall speed
+fn handle_jump_arc(
+    physics_conf: Res<PhysicsConfig>,
+    tuning: Res<PlayerTuning>,
+    mut players: Query<(
+        &mut Velocity,
+        &mut GravityScale,
+        &PlayerInputState,
+        &StateMachine<PlayerState>,
+    )>,
+) {
+    // The gravity scale is relative to the world gravity
+    let world_gravity = physics_conf.gravity.length();
+    if world_gravity == 0.0 {
+        return;
+    }
+
+    for (mut velocity, mut gravity_scale, input, machine) in players.iter_mut() {
+        match machine.current() {
+            // Dashes ignore gravity
+            PlayerState::Dash => {
+                gravity_scale.0 = 0.0;
+                continue;
+            }
+            // Ground pounds hang and then fall faster than the terminal velocity
+            PlayerState::GroundPound => {
+                gravity_scale.0 = 0.0;
+                continue;
+            }
+            // Hanging and climbing players hold their position
+            PlayerState::LedgeHang | PlayerState::Climbing => {
+                gravity_scale.0 = 0.0;
+                continue;
+            }
+            _ => {}
+        }
+
+        // Cut the jump short when the button is released while rising
+        if input.is_jump_released && velocity.0.y > 0.0 {
+            velocity.0.y *= tuning.jump_cut;
+        }
+
+        // Use a heavier gravity when falling for a snappier arc
+        let multiplier = if velocity.0.y > 0.0 {
+            tuning.rise_gravity_multiplier
+        } else {
+            tuning.fall_gravity_multiplier
         };
+        gravity_scale.0 = tuning.jump_gravity() / world_gravity * multiplier;
+
+        // Cap the fall speed
+        velocity.0.y = velocity.0.y.max(-tuning.terminal_velocity);
     }
 }
@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::{
+    Action, ActionState, GameState, GameTime, JoinedPlayers, LevelId, PlayerDevice, PlayerIndex,
+};
+
+/// The environment variable with the path to record the session to
+pub const RECORD_ENV: &str = "HIGHGROUND_RECORD";
+
+/// The environment variable with the path of the recording to replay
+pub const REPLAY_ENV: &str = "HIGHGROUND_REPLAY";
+
+/// The amount of frames between the checkpoints
+const CHECKPOINT_INTERVAL: usize = 60;
+
+/// The amount of frames between the saves of a recording, so a crash loses at most this many frames
+const SAVE_INTERVAL: usize = 600;
+
+/// The distance a replayed player may be off from a checkpoint before it counts as diverged
+const DIVERGENCE_TOLERANCE: f32 = 0.5;
+
+/// The positions of the players at a frame, used to detect diverging replays
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    // The frame the checkpoint was taken at
+    pub frame: usize,
+    // The positions of the players by player index
+    pub positions: Vec<Vec2>,
+}
+
+/// A recorded gameplay frame
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    // The time in seconds the gameplay advanced by
+    pub delta: f32,
+    // The analog action values of the players by player index
+    pub actions: Vec<HashMap<Action, f32>>,
+}
+
+/// A recorded session
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    // The level the session was played in
+    pub level: String,
+    // The amount of players in the session
+    pub players: usize,
+    // The frame times and actions of every gameplay frame
+    pub frames: Vec<RecordedFrame>,
+    // The positions of the players at regular intervals
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl Recording {
+    /// Load a recording from a ron file
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(ron::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Save the recording to a ron file
+    /// Written next to the file first, so a crash while saving doesn't break the previous save
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, ron::ser::to_string(self)?)?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+}
+
+/// What the replay system is doing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayMode {
+    // Playing normally
+    Off,
+    // Recording the session to the path
+    Recording(PathBuf),
+    // Feeding the recording back into the players
+    Replaying,
+}
+
+/// The recording or replaying of the session
+#[derive(Debug, Clone)]
+pub struct Replay {
+    // What the replay system is doing
+    pub mode: ReplayMode,
+    // The session that is being recorded or replayed
+    pub recording: Recording,
+    // The current gameplay frame
+    pub frame: usize,
+}
+
+impl Replay {
+    /// Set up recording or replaying from the environment variables
+    pub fn from_env() -> Self {
+        let mut replay = Self {
+            mode: ReplayMode::Off,
+            recording: Recording::default(),
+            frame: 0,
+        };
+
+        if let Some(path) = env::var_os(REPLAY_ENV).map(PathBuf::from) {
+            match Recording::load(&path) {
+                Ok(recording) => {
+                    info!("Replaying {:?}", path);
+                    replay.mode = ReplayMode::Replaying;
+                    replay.recording = recording;
+                }
+                Err(err) => warn!("Failed to load the recording {:?}: {}", path, err),
+            }
+        } else if let Some(path) = env::var_os(RECORD_ENV).map(PathBuf::from) {
+            info!("Recording to {:?}", path);
+            replay.mode = ReplayMode::Recording(path);
+        }
+        replay
+    }
+}
+
+/// Sent when a replayed player is not where the recording says it should be
+#[derive(Debug, Clone)]
+pub struct ReplayDivergedEvent {
+    pub frame: usize,
+    pub player: usize,
+    pub expected: Vec2,
+    pub actual: Vec2,
+}
+
+/// The plugin for recording and replaying sessions
+/// Enabled by setting [`RECORD_ENV`] or [`REPLAY_ENV`] to a file path
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Replay::from_env());
+        app.add_event::<ReplayDivergedEvent>();
+
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            replay_frame_time.after("update_game_time"),
+        );
+        app.add_system_set(SystemSet::on_update(GameState::Join).with_system(start_replay));
+        // The checkpoints are taken before the physics, players or deaths move anyone this frame
+        app.add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(
+                    record_inputs
+                        .label("record_inputs")
+                        .after("update_action_states")
+                        .before("apply_gravity")
+                        .before("update_input_state")
+                        .before("handle_deaths"),
+                )
+                .with_system(
+                    replay_inputs
+                        .label("replay_inputs")
+                        .after("update_action_states")
+                        .before("apply_gravity")
+                        .before("update_input_state")
+                        .before("handle_deaths"),
+                ),
+        );
+        app.add_system_to_stage(CoreStage::Last, save_recording);
+    }
+}
+
+/// Join the recorded players and skip the join screen when replaying
+fn start_replay(
+    replay: Res<Replay>,
+    level: Res<LevelId>,
+    mut joined: ResMut<JoinedPlayers>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if replay.mode != ReplayMode::Replaying {
+        return;
+    }
+    if replay.recording.level != level.0 {
+        warn!(
+            "The recording was made in level {:?} but level {:?} is loaded",
+            replay.recording.level, level.0
+        );
+    }
+
+    // The devices are placeholders, the inputs come from the recording
+    joined.0 = (0..replay.recording.players)
+        .map(|index| PlayerDevice::Gamepad(Gamepad(index)))
+        .collect();
+    // A transition may already be queued this frame
+    let _ = state.set(GameState::Gameplay);
+}
+
+/// The action values and positions of the players, ordered by player index
+fn sorted_players<'a>(
+    players: impl Iterator<Item = (&'a PlayerIndex, &'a Transform, &'a ActionState)>,
+) -> Vec<(HashMap<Action, f32>, Vec2)> {
+    let mut players = players.collect::<Vec<_>>();
+    players.sort_by_key(|(index, _, _)| index.0);
+    players
+        .into_iter()
+        .map(|(_, transform, actions)| (actions.values().clone(), transform.translation.truncate()))
+        .collect()
+}
+
+/// Step the gameplay with the recorded frame time of the frame that is replayed next
+fn replay_frame_time(replay: Res<Replay>, mut game_time: ResMut<GameTime>) {
+    if replay.mode != ReplayMode::Replaying {
+        return;
+    }
+    if let Some(frame) = replay.recording.frames.get(replay.frame) {
+        game_time.set_delta_seconds(frame.delta);
+    }
+}
+
+/// Record the actions of the players, and their positions at every checkpoint
+/// The recording is saved at regular intervals, so it survives a crash
+fn record_inputs(
+    mut replay: ResMut<Replay>,
+    game_time: Res<GameTime>,
+    level: Res<LevelId>,
+    players: Query<(&PlayerIndex, &Transform, &ActionState)>,
+) {
+    if !matches!(replay.mode, ReplayMode::Recording(_)) {
+        return;
+    }
+
+    let players = sorted_players(players.iter());
+    if players.is_empty() {
+        return;
+    }
+
+    let frame = replay.frame;
+    let recording = &mut replay.recording;
+    if frame == 0 {
+        recording.level = level.0.clone();
+        recording.players = players.len();
+    }
+    if frame % CHECKPOINT_INTERVAL == 0 {
+        recording.checkpoints.push(Checkpoint {
+            frame,
+            positions: players.iter().map(|(_, position)| *position).collect(),
+        });
+    }
+    recording.frames.push(RecordedFrame {
+        delta: game_time.delta_seconds(),
+        actions: players.into_iter().map(|(values, _)| values).collect(),
+    });
+    replay.frame += 1;
+
+    if replay.frame % SAVE_INTERVAL == 0 {
+        save(&replay);
+    }
+}
+
+/// Overwrite the actions of the players with the recorded actions, and check the checkpoints
+fn replay_inputs(
+    mut replay: ResMut<Replay>,
+    mut diverged_events: EventWriter<ReplayDivergedEvent>,
+    mut players: Query<(&PlayerIndex, &Transform, &mut ActionState)>,
+) {
+    if replay.mode != ReplayMode::Replaying || players.iter().next().is_none() {
+        return;
+    }
+
+    let frame = replay.frame;
+    let values = match replay.recording.frames.get(frame) {
+        Some(recorded) => recorded.actions.clone(),
+        None => {
+            info!("Replay finished after {} frames", frame);
+            replay.mode = ReplayMode::Off;
+            return;
+        }
+    };
+
+    // Compare the positions before this frame moves the players, like when recording
+    let checkpoint = replay
+        .recording
+        .checkpoints
+        .iter()
+        .find(|checkpoint| checkpoint.frame == frame);
+    for (index, transform, mut actions) in players.iter_mut() {
+        if let Some(expected) = checkpoint.and_then(|checkpoint| checkpoint.positions.get(index.0))
+        {
+            let actual = transform.translation.truncate();
+            if actual.distance(*expected) > DIVERGENCE_TOLERANCE {
+                warn!(
+                    "Replay diverged at frame {} for player {}: expected {}, got {}",
+                    frame,
+                    index.0 + 1,
+                    expected,
+                    actual
+                );
+                diverged_events.send(ReplayDivergedEvent {
+                    frame,
+                    player: index.0,
+                    expected: *expected,
+                    actual,
+                });
+            }
+        }
+
+        // The live input already advanced the frame, so only its values are replaced
+        actions.set(values.get(index.0).cloned().unwrap_or_default());
+    }
+    replay.frame += 1;
+}
+
+/// Save the recording when the game exits
+fn save_recording(mut exit_events: EventReader<AppExit>, replay: Res<Replay>) {
+    if exit_events.iter().next().is_some() {
+        save(&replay);
+    }
+}
+
+/// Save the recording to the path it is recorded to
+fn save(replay: &Replay) {
+    if let ReplayMode::Recording(path) = &replay.mode {
+        match replay.recording.save(path) {
+            Ok(()) => info!("Saved the recording to {:?}", path),
+            Err(err) => warn!("Failed to save the recording to {:?}: {}", path, err),
+        }
+    }
+}
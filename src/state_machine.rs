@@ -0,0 +1,169 @@
+use std::{fmt::Debug, hash::Hash, marker::PhantomData};
+
+use bevy::prelude::*;
+
+use crate::prelude::{GameState, GameTime};
+
+/// A state that can be used in a [`StateMachine`]
+pub trait MachineState:
+    Debug + Clone + Copy + PartialEq + Eq + Hash + Send + Sync + 'static
+{
+    /// The parent of the state in the hierarchy, if any
+    fn parent(&self) -> Option<Self> {
+        None
+    }
+
+    /// The guard for the transition into the next state
+    fn can_transition(&self, _next: &Self) -> bool {
+        true
+    }
+}
+
+/// A hierarchical state machine component
+/// Transitions are queued and turned into enter and exit events by [`StateMachinePlugin`]
+#[derive(Debug, Clone, Component)]
+pub struct StateMachine<S: MachineState> {
+    current: S,
+    previous: Option<S>,
+    time_in_state: f32,
+    transitions: Vec<(S, S)>,
+}
+
+impl<S: MachineState> StateMachine<S> {
+    /// Create a new state machine in the initial state
+    pub fn new(initial: S) -> Self {
+        Self {
+            current: initial,
+            previous: None,
+            time_in_state: 0.0,
+            transitions: Vec::new(),
+        }
+    }
+
+    /// The current state
+    pub fn current(&self) -> S {
+        self.current
+    }
+
+    /// The state before the current state
+    pub fn previous(&self) -> Option<S> {
+        self.previous
+    }
+
+    /// The time in seconds since the current state was entered
+    pub fn time_in_state(&self) -> f32 {
+        self.time_in_state
+    }
+
+    /// Check if the current state is the given state or one of its children
+    pub fn is_in(&self, state: S) -> bool {
+        path(self.current).contains(&state)
+    }
+
+    /// Transition into the next state if the guard allows it
+    /// Returns true if the state changed
+    pub fn transition(&mut self, next: S) -> bool {
+        if next == self.current || !self.current.can_transition(&next) {
+            return false;
+        }
+
+        self.transitions.push((self.current, next));
+        self.previous = Some(self.current);
+        self.current = next;
+        self.time_in_state = 0.0;
+        true
+    }
+}
+
+/// The path from a state up to the root of the hierarchy
+fn path<S: MachineState>(state: S) -> Vec<S> {
+    let mut path = vec![state];
+    while let Some(parent) = path[path.len() - 1].parent() {
+        path.push(parent);
+    }
+    path
+}
+
+/// Sent when an entity enters a state, parent states are entered before their children
+#[derive(Debug, Clone)]
+pub struct StateEnterEvent<S: MachineState> {
+    pub entity: Entity,
+    pub state: S,
+    pub previous: S,
+}
+
+/// Sent when an entity exits a state, child states are exited before their parents
+#[derive(Debug, Clone)]
+pub struct StateExitEvent<S: MachineState> {
+    pub entity: Entity,
+    pub state: S,
+    pub next: S,
+}
+
+/// The plugin that runs the [`StateMachine`]s for a state type
+pub struct StateMachinePlugin<S: MachineState>(PhantomData<S>);
+
+impl<S: MachineState> Default for StateMachinePlugin<S> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<S: MachineState> Plugin for StateMachinePlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StateEnterEvent<S>>();
+        app.add_event::<StateExitEvent<S>>();
+
+        // Run after the update so all transitions of the frame are collected
+        app.add_system_to_stage(CoreStage::PostUpdate, update_state_machines::<S>);
+    }
+}
+
+/// Send the events for the queued transitions and track the time in state
+/// The time in state only runs during gameplay, so it stands still while paused
+fn update_state_machines<S: MachineState>(
+    time: Res<GameTime>,
+    state: Res<State<GameState>>,
+    mut enter_events: EventWriter<StateEnterEvent<S>>,
+    mut exit_events: EventWriter<StateExitEvent<S>>,
+    mut machines: Query<(Entity, &mut StateMachine<S>)>,
+) {
+    let delta = if *state.current() == GameState::Gameplay {
+        time.delta_seconds()
+    } else {
+        0.0
+    };
+
+    for (entity, mut machine) in machines.iter_mut() {
+        if machine.transitions.is_empty() {
+            machine.time_in_state += delta;
+            continue;
+        }
+
+        for (from, to) in std::mem::take(&mut machine.transitions) {
+            let from_path = path(from);
+            let to_path = path(to);
+
+            // Exit the states that are not shared, from the leaf up
+            for state in from_path.iter().filter(|state| !to_path.contains(state)) {
+                exit_events.send(StateExitEvent {
+                    entity,
+                    state: *state,
+                    next: to,
+                });
+            }
+            // Enter the states that are not shared, from the root down
+            for state in to_path
+                .iter()
+                .rev()
+                .filter(|state| !from_path.contains(state))
+            {
+                enter_events.send(StateEnterEvent {
+                    entity,
+                    state: *state,
+                    previous: from,
+                });
+            }
+        }
+    }
+}
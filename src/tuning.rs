@@ -0,0 +1,238 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::Deserialize;
+
+/// The path to the player tuning, relative to the assets folder
+pub const PLAYER_TUNING_PATH: &str = "tuning/player.tuning.ron";
+
+/// The movement tuning for the player
+/// Loaded from a `.tuning.ron` file and hot-reloaded while playing
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "6c1f2b8e-4d3a-4f0e-9a57-2e8b51c3d7a4"]
+#[serde(default)]
+pub struct PlayerTuning {
+    /// The top speed when walking
+    pub max_walk_speed: f32,
+    /// The top speed when running
+    pub max_run_speed: f32,
+    /// The horizontal acceleration when walking
+    pub walk_accel: f32,
+    /// The horizontal acceleration when running
+    pub run_accel: f32,
+    /// The horizontal deceleration when there is no input or above the top speed
+    pub decel: f32,
+    /// The horizontal deceleration when turning around
+    pub skid_decel: f32,
+    /// The minimum speed at which turning around makes the player skid
+    pub skid_threshold: f32,
+    /// The acceleration multiplier while airborne
+    pub air_accel_multiplier: f32,
+    /// The deceleration multiplier while airborne
+    pub air_decel_multiplier: f32,
+    /// The height of a full jump
+    pub jump_height: f32,
+    /// The time it takes a full jump to reach its apex
+    pub jump_time_to_apex: f32,
+    /// The factor the upwards velocity is multiplied with when jump is released early
+    pub jump_cut: f32,
+    /// The gravity multiplier while rising
+    pub rise_gravity_multiplier: f32,
+    /// The gravity multiplier while falling
+    pub fall_gravity_multiplier: f32,
+    /// The maximum fall speed
+    pub terminal_velocity: f32,
+    /// The maximum fall speed while sliding down a wall
+    pub wall_slide_speed: f32,
+    /// The horizontal speed away from the wall when wall jumping
+    pub wall_jump_horizontal_speed: f32,
+    /// The upwards speed when wall jumping
+    pub wall_jump_vertical_speed: f32,
+    /// The time in seconds the horizontal input is locked after a wall jump
+    pub wall_jump_lock_time: f32,
+    /// The height of the double jump
+    pub double_jump_height: f32,
+    /// The horizontal speed of a dash
+    pub dash_speed: f32,
+    /// The time in seconds a dash lasts
+    pub dash_duration: f32,
+    /// The time in seconds before the player can dash again
+    pub dash_cooldown: f32,
+    /// The time in seconds the player is invulnerable when dashing
+    pub dash_invulnerability: f32,
+    /// The fall speed of a ground pound
+    pub ground_pound_speed: f32,
+    /// The time in seconds the player hangs in the air before a ground pound falls
+    pub ground_pound_hang: f32,
+    /// The jump velocity multiplier for a perfectly timed jump out of a ground pound landing
+    pub ground_pound_jump_multiplier: f32,
+    /// The height of the crouching collider relative to the standing collider
+    pub crouch_height_ratio: f32,
+    /// The top speed when crouching
+    pub crouch_speed: f32,
+    /// The maximum distance between the top of the player and a ledge to grab it
+    pub ledge_grab_reach: f32,
+    /// The time in seconds before the player can grab a ledge again after dropping from one
+    pub ledge_regrab_cooldown: f32,
+    /// The speed when climbing
+    pub climb_speed: f32,
+    /// The time in seconds before the player can grab a climbable again after jumping off
+    pub climb_regrab_cooldown: f32,
+    /// The time in seconds before the attack hitbox comes out
+    pub attack_startup: f32,
+    /// The time in seconds the attack hitbox is out
+    pub attack_active: f32,
+    /// The time in seconds after the hitbox before the player can act again
+    pub attack_recovery: f32,
+    /// The damage dealt by the attack
+    pub attack_damage: f32,
+    /// The knockback of the attack when facing right
+    pub attack_knockback: Vec2,
+    /// The offset of the attack hitbox from the player when facing right
+    pub attack_hitbox_offset: Vec2,
+    /// The half extents of the attack hitbox
+    pub attack_hitbox_half_extents: Vec2,
+    /// The damage multiplier of the attack when it finishes the power strike combo
+    pub power_strike_multiplier: f32,
+}
+
+impl Default for PlayerTuning {
+    fn default() -> Self {
+        Self {
+            max_walk_speed: 72.0,
+            max_run_speed: 104.0,
+            walk_accel: 480.0,
+            run_accel: 640.0,
+            decel: 600.0,
+            skid_decel: 900.0,
+            skid_threshold: 40.0,
+            air_accel_multiplier: 0.65,
+            air_decel_multiplier: 0.3,
+            jump_height: 48.0,
+            jump_time_to_apex: 0.55,
+            jump_cut: 0.5,
+            rise_gravity_multiplier: 1.0,
+            fall_gravity_multiplier: 1.6,
+            terminal_velocity: 320.0,
+            wall_slide_speed: 48.0,
+            wall_jump_horizontal_speed: 110.0,
+            wall_jump_vertical_speed: 160.0,
+            wall_jump_lock_time: 0.15,
+            double_jump_height: 36.0,
+            dash_speed: 220.0,
+            dash_duration: 0.18,
+            dash_cooldown: 0.6,
+            dash_invulnerability: 0.2,
+            ground_pound_speed: 400.0,
+            ground_pound_hang: 0.1,
+            ground_pound_jump_multiplier: 1.5,
+            crouch_height_ratio: 0.5,
+            crouch_speed: 36.0,
+            ledge_grab_reach: 6.0,
+            ledge_regrab_cooldown: 0.3,
+            climb_speed: 60.0,
+            climb_regrab_cooldown: 0.3,
+            attack_startup: 0.1,
+            attack_active: 0.12,
+            attack_recovery: 0.2,
+            attack_damage: 1.0,
+            attack_knockback: Vec2::new(120.0, 80.0),
+            attack_hitbox_offset: Vec2::new(18.0, 0.0),
+            attack_hitbox_half_extents: Vec2::new(10.0, 10.0),
+            power_strike_multiplier: 2.0,
+        }
+    }
+}
+
+impl PlayerTuning {
+    /// The gravity needed to reach the jump height in the time to apex
+    pub fn jump_gravity(&self) -> f32 {
+        2.0 * self.jump_height / (self.jump_time_to_apex * self.jump_time_to_apex)
+    }
+
+    /// The initial upwards velocity of a full jump
+    pub fn jump_velocity(&self) -> f32 {
+        2.0 * self.jump_height / self.jump_time_to_apex
+    }
+
+    /// The initial upwards velocity of the double jump
+    pub fn double_jump_velocity(&self) -> f32 {
+        (2.0 * self.jump_gravity() * self.double_jump_height).sqrt()
+    }
+}
+
+/// The handle to the loaded player tuning
+#[derive(Debug, Clone)]
+pub struct PlayerTuningHandle(pub Handle<PlayerTuning>);
+
+/// Loads [`PlayerTuning`] from ron files
+#[derive(Debug, Default)]
+pub struct PlayerTuningLoader;
+
+impl AssetLoader for PlayerTuningLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let tuning = ron::de::from_bytes::<PlayerTuning>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(tuning));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tuning.ron"]
+    }
+}
+
+/// The plugin that loads and hot-reloads the tuning assets
+pub struct TuningPlugin;
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        // The active tuning starts out with the defaults until the asset is loaded
+        app.init_resource::<PlayerTuning>();
+        app.add_asset::<PlayerTuning>();
+        app.init_asset_loader::<PlayerTuningLoader>();
+
+        app.add_startup_system(load_tuning);
+        app.add_system(update_tuning);
+    }
+}
+
+/// Start loading the tuning assets
+fn load_tuning(asset_server: Res<AssetServer>, mut commands: Commands) {
+    commands.insert_resource(PlayerTuningHandle(asset_server.load(PLAYER_TUNING_PATH)));
+}
+
+/// Copy the tuning asset into the active tuning when it is loaded or modified
+fn update_tuning(
+    mut events: EventReader<AssetEvent<PlayerTuning>>,
+    handle: Option<Res<PlayerTuningHandle>>,
+    assets: Res<Assets<PlayerTuning>>,
+    mut tuning: ResMut<PlayerTuning>,
+) {
+    let handle = match handle {
+        Some(handle) => handle,
+        None => return,
+    };
+
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed } => {
+                if *changed != handle.0 {
+                    continue;
+                }
+                if let Some(loaded) = assets.get(changed) {
+                    info!("Reloaded player tuning");
+                    *tuning = loaded.clone();
+                }
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+}